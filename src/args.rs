@@ -0,0 +1,348 @@
+use std::fs;
+use std::io;
+
+use thiserror::Error;
+
+use codecrafters_grep::error::GlobError;
+use codecrafters_grep::glob::Glob;
+
+use crate::color::ColorChoice;
+use crate::walk::Rule;
+
+#[derive(Debug, Error)]
+pub enum ArgsError {
+    #[error("invalid option -- '{0}'")]
+    UnknownShort(char),
+    #[error("unrecognized option '--{0}'")]
+    UnknownLong(String),
+    #[error("option requires an argument -- '{0}'")]
+    MissingValue(String),
+    #[error("option '--{0}' doesn't allow an argument")]
+    UnexpectedValue(String),
+    #[error("{0}: invalid context length argument")]
+    InvalidContextLength(String),
+    #[error("invalid argument '{0}' for '--color'\nValid arguments are:\n  - 'always', 'yes', 'force'\n  - 'never', 'no', 'none'\n  - 'auto', 'tty', 'if-tty'")]
+    InvalidColor(String),
+    #[error("invalid argument '{0}' for '--binary-files'\nValid arguments are:\n  - 'binary'\n  - 'text'\n  - 'without-match'")]
+    InvalidBinaryFiles(String),
+    #[error("invalid glob '{glob}': {source}")]
+    InvalidGlob { glob: String, source: GlobError },
+    #[error("invalid argument '{0}' for '--sort'\nValid arguments are:\n  - 'path'\n  - 'none'\n  - 'modified'\n  - 'size'")]
+    InvalidSort(String),
+    #[error("{0}: invalid max depth argument")]
+    InvalidMaxDepth(String),
+    #[error("{0}: invalid number of threads")]
+    InvalidThreads(String),
+    #[error("no pattern given")]
+    MissingPattern,
+    #[error("{path}: {source}")]
+    PatternFile { path: String, source: io::Error },
+}
+
+struct OptionSpec {
+    short: Option<char>,
+    long: &'static str,
+    value: Option<&'static str>,
+    // the value of a long option that may be given without one, as in
+    // `--color`; such a value can only be attached with `=`. On a short
+    // option without a value it is the value implied for its long name,
+    // `-I` being `--binary-files=without-match`
+    default: Option<&'static str>,
+    help: &'static str,
+}
+
+// every option we understand; short flags are resolved to their long name
+// before being applied, and --help is generated from this table
+const OPTIONS: &[OptionSpec] = &[
+    OptionSpec { short: Some('E'), long: "extended-regexp", value: None, default: None, help: "PATTERNS are extended regular expressions (the default)" },
+    OptionSpec { short: Some('e'), long: "regexp", value: Some("PATTERNS"), default: None, help: "use PATTERNS for matching" },
+    OptionSpec { short: Some('f'), long: "file", value: Some("FILE"), default: None, help: "take PATTERNS from FILE" },
+    OptionSpec { short: Some('v'), long: "invert-match", value: None, default: None, help: "select non-matching lines" },
+    OptionSpec { short: Some('c'), long: "count", value: None, default: None, help: "print only a count of selected lines per FILE" },
+    OptionSpec { short: Some('n'), long: "line-number", value: None, default: None, help: "print line number with output lines" },
+    OptionSpec { short: Some('b'), long: "byte-offset", value: None, default: None, help: "print the byte offset with output lines" },
+    OptionSpec { short: Some('A'), long: "after-context", value: Some("NUM"), default: None, help: "print NUM lines of trailing context" },
+    OptionSpec { short: Some('B'), long: "before-context", value: Some("NUM"), default: None, help: "print NUM lines of leading context" },
+    OptionSpec { short: Some('C'), long: "context", value: Some("NUM"), default: None, help: "print NUM lines of output context" },
+    OptionSpec { short: None, long: "binary-files", value: Some("TYPE"), default: None, help: "assume that binary files are TYPE; TYPE is 'binary', 'text', or 'without-match'" },
+    OptionSpec { short: Some('a'), long: "text", value: None, default: None, help: "equivalent to --binary-files=text" },
+    OptionSpec { short: Some('I'), long: "binary-files", value: None, default: Some("without-match"), help: "equivalent to --binary-files=without-match" },
+    OptionSpec { short: Some('i'), long: "ignore-case", value: None, default: None, help: "ignore case distinctions in patterns and data" },
+    OptionSpec { short: Some('o'), long: "only-matching", value: None, default: None, help: "show only nonempty parts of lines that match" },
+    OptionSpec { short: None, long: "color", value: Some("WHEN"), default: Some("auto"), help: "use markers to highlight the matching strings; WHEN is 'always', 'never', or 'auto'" },
+    OptionSpec { short: Some('s'), long: "no-messages", value: None, default: None, help: "suppress error messages" },
+    OptionSpec { short: Some('r'), long: "recursive", value: None, default: None, help: "search directories recursively, skipping symlinks inside them" },
+    OptionSpec { short: Some('R'), long: "follow", value: None, default: None, help: "likewise, but follow all symlinks" },
+    OptionSpec { short: None, long: "include", value: Some("GLOB"), default: None, help: "search only files whose name matches GLOB" },
+    OptionSpec { short: None, long: "exclude", value: Some("GLOB"), default: None, help: "skip files whose name matches GLOB" },
+    OptionSpec { short: None, long: "exclude-dir", value: Some("GLOB"), default: None, help: "skip directories whose name matches GLOB" },
+    OptionSpec { short: Some('g'), long: "glob", value: Some("GLOB"), default: None, help: "include or, with a leading '!', exclude paths matching GLOB" },
+    OptionSpec { short: None, long: "sort", value: Some("SORTBY"), default: None, help: "search files in SORTBY order; SORTBY is 'path', 'none', 'modified', or 'size'" },
+    OptionSpec { short: None, long: "max-depth", value: Some("NUM"), default: None, help: "descend at most NUM levels below the command line arguments" },
+    OptionSpec { short: None, long: "hidden", value: None, default: None, help: "search hidden files and directories with -r" },
+    OptionSpec { short: None, long: "no-ignore", value: None, default: None, help: "don't respect .gitignore, .ignore and .git/info/exclude with -r" },
+    OptionSpec { short: Some('j'), long: "threads", value: Some("NUM"), default: None, help: "search with NUM threads; 0, the default, uses one per CPU" },
+    OptionSpec { short: Some('V'), long: "version", value: None, default: None, help: "display version information and exit" },
+    OptionSpec { short: None, long: "help", value: None, default: None, help: "display this help text and exit" },
+];
+
+/// What to do with inputs that look binary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BinaryFiles {
+    /// Print "Binary file X matches" instead of the selected lines.
+    #[default]
+    Binary,
+    Text,
+    WithoutMatch,
+}
+
+/// The order in which the entries of a directory are searched with -r.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    #[default]
+    Path,
+    /// Whatever order the file system lists them in.
+    None,
+    Modified,
+    Size,
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub patterns: Vec<String>,
+    pub paths: Vec<String>,
+    pub recursive: bool,
+    pub follow: bool,
+    pub only_matching: bool,
+    pub ignore_case: bool,
+    pub invert_match: bool,
+    pub count: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub after_context: Option<usize>,
+    pub before_context: Option<usize>,
+    pub context: Option<usize>,
+    pub color: ColorChoice,
+    pub no_messages: bool,
+    pub binary_files: BinaryFiles,
+    pub hidden: bool,
+    pub no_ignore: bool,
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+    pub exclude_dir: Vec<Glob>,
+    pub globs: Vec<Rule>,
+    pub sort: SortBy,
+    pub max_depth: Option<usize>,
+    pub threads: usize,
+}
+
+pub enum Command {
+    Search(Box<Args>),
+    Help,
+    Version,
+}
+
+impl Args {
+    fn apply(&mut self, long: &str, value: Option<String>) -> Result<Option<Command>, ArgsError> {
+        match long {
+            "extended-regexp" => {}
+            "regexp" => self.patterns.extend(value.unwrap().split('\n').map(String::from)),
+            "file" => {
+                let path = value.unwrap();
+                let contents = fs::read_to_string(&path)
+                    .map_err(|source| ArgsError::PatternFile { path, source })?;
+                self.patterns.extend(contents.lines().map(String::from));
+            }
+            "invert-match" => self.invert_match = true,
+            "count" => self.count = true,
+            "line-number" => self.line_number = true,
+            "byte-offset" => self.byte_offset = true,
+            "after-context" => self.after_context = Some(context_length(value.unwrap())?),
+            "before-context" => self.before_context = Some(context_length(value.unwrap())?),
+            "context" => self.context = Some(context_length(value.unwrap())?),
+            "binary-files" => {
+                self.binary_files = match value.unwrap().as_str() {
+                    "binary" => BinaryFiles::Binary,
+                    "text" => BinaryFiles::Text,
+                    "without-match" => BinaryFiles::WithoutMatch,
+                    other => return Err(ArgsError::InvalidBinaryFiles(other.to_string())),
+                }
+            }
+            "text" => self.binary_files = BinaryFiles::Text,
+            "ignore-case" => self.ignore_case = true,
+            "only-matching" => self.only_matching = true,
+            "color" => {
+                let value = value.unwrap();
+                self.color = value.parse().map_err(|_| ArgsError::InvalidColor(value))?;
+            }
+            "no-messages" => self.no_messages = true,
+            "recursive" => self.recursive = true,
+            "follow" => {
+                self.recursive = true;
+                self.follow = true;
+            }
+            "include" => self.include.push(glob(value.unwrap())?),
+            "exclude" => self.exclude.push(glob(value.unwrap())?),
+            "exclude-dir" => self.exclude_dir.push(glob(value.unwrap())?),
+            "glob" => {
+                let value = value.unwrap();
+                match Rule::parse(&value) {
+                    Ok(rule) => self.globs.extend(rule),
+                    Err(source) => return Err(ArgsError::InvalidGlob { glob: value, source }),
+                }
+            }
+            "sort" => {
+                self.sort = match value.unwrap().as_str() {
+                    "path" => SortBy::Path,
+                    "none" => SortBy::None,
+                    "modified" => SortBy::Modified,
+                    "size" => SortBy::Size,
+                    other => return Err(ArgsError::InvalidSort(other.to_string())),
+                }
+            }
+            "max-depth" => {
+                let value = value.unwrap();
+                self.max_depth = Some(value.parse().map_err(|_| ArgsError::InvalidMaxDepth(value))?);
+            }
+            "threads" => {
+                let value = value.unwrap();
+                self.threads = value.parse().map_err(|_| ArgsError::InvalidThreads(value))?;
+            }
+            "hidden" => self.hidden = true,
+            "no-ignore" => self.no_ignore = true,
+            "version" => return Ok(Some(Command::Version)),
+            "help" => return Ok(Some(Command::Help)),
+            _ => unreachable!("option table and apply are out of sync: {}", long),
+        }
+        Ok(None)
+    }
+
+    /// Lines of trailing context; an explicit -A wins over -C.
+    pub fn after_lines(&self) -> usize {
+        self.after_context.or(self.context).unwrap_or(0)
+    }
+
+    /// Lines of leading context; an explicit -B wins over -C.
+    pub fn before_lines(&self) -> usize {
+        self.before_context.or(self.context).unwrap_or(0)
+    }
+}
+
+fn glob(value: String) -> Result<Glob, ArgsError> {
+    Glob::new(&value).map_err(|source| ArgsError::InvalidGlob { glob: value, source })
+}
+
+fn context_length(value: String) -> Result<usize, ArgsError> {
+    value.parse().map_err(|_| ArgsError::InvalidContextLength(value))
+}
+
+/// Parses the command line (without the program name). Options may appear
+/// anywhere, short ones may be clustered (`-ro`), and everything after `--`
+/// is an operand.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, ArgsError> {
+    let mut parsed = Args::default();
+    let mut operands = Vec::new();
+    let mut has_pattern_option = false;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            operands.extend(args.by_ref());
+            break;
+        }
+
+        let command = if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline_value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let spec = OPTIONS
+                .iter()
+                .find(|spec| spec.long == name)
+                .ok_or_else(|| ArgsError::UnknownLong(name.to_string()))?;
+            let value = match (spec.value, inline_value) {
+                (Some(_), Some(value)) => Some(value),
+                (Some(_), None) if spec.default.is_some() => spec.default.map(String::from),
+                (Some(_), None) => Some(args.next().ok_or_else(|| ArgsError::MissingValue(name.to_string()))?),
+                (None, Some(_)) => return Err(ArgsError::UnexpectedValue(name.to_string())),
+                (None, None) => None,
+            };
+            has_pattern_option |= matches!(spec.long, "regexp" | "file");
+            parsed.apply(spec.long, value)?
+        }
+        else if arg.len() > 1 && arg.starts_with('-') {
+            let mut command = None;
+            for (index, c) in arg[1..].char_indices() {
+                let spec = OPTIONS
+                    .iter()
+                    .find(|spec| spec.short == Some(c))
+                    .ok_or(ArgsError::UnknownShort(c))?;
+                let value = if spec.value.is_some() {
+                    // the rest of the cluster is the value, `-e foo` or `-efoo`
+                    let rest = &arg[1 + index + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next().ok_or_else(|| ArgsError::MissingValue(c.to_string()))?
+                    }
+                    else {
+                        rest.to_string()
+                    };
+                    Some(value)
+                }
+                else {
+                    spec.default.map(String::from)
+                };
+                has_pattern_option |= matches!(spec.long, "regexp" | "file");
+                command = command.or(parsed.apply(spec.long, value.clone())?);
+                if spec.value.is_some() {
+                    break;
+                }
+            }
+            command
+        }
+        else {
+            operands.push(arg);
+            None
+        };
+
+        if let Some(command) = command {
+            return Ok(command);
+        }
+    }
+
+    let mut operands = operands.into_iter();
+    if !has_pattern_option {
+        let pattern = operands.next().ok_or(ArgsError::MissingPattern)?;
+        parsed.patterns.push(pattern);
+    }
+    parsed.paths.extend(operands);
+    Ok(Command::Search(Box::new(parsed)))
+}
+
+pub fn usage() -> String {
+    format!("Usage: {} [OPTION]... PATTERNS [FILE]...", env!("CARGO_PKG_NAME"))
+}
+
+pub fn help() -> String {
+    let mut text = usage();
+    text.push_str("\nSearch for PATTERNS in each FILE.\n\nOptions:\n");
+    for spec in OPTIONS {
+        let long = match (spec.value, spec.default) {
+            (Some(value), Some(_)) => format!("--{}[={}]", spec.long, value),
+            (Some(value), None) => format!("--{}={}", spec.long, value),
+            // a short alias for a value of an earlier long option
+            (None, Some(_)) => String::new(),
+            (None, None) => format!("--{}", spec.long),
+        };
+        let short = match spec.short {
+            Some(c) if long.is_empty() => format!("-{}  ", c),
+            Some(c) => format!("-{}, ", c),
+            None => "    ".to_string(),
+        };
+        text.push_str(&format!("  {}{:<26}{}\n", short, long, spec.help));
+    }
+    text
+}
+
+pub fn version() -> String {
+    format!("{} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
@@ -0,0 +1,104 @@
+/// A single member of a bracketed character class, or one of the escape
+/// classes (`\d`, `\w`, `\s`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Digit,
+    Word,
+    Space,
+}
+
+/// Simple (one char to one char) Unicode case folding. Chars whose case
+/// mapping expands to several chars, like `ß`, fold to themselves.
+pub fn fold_case(c: char) -> char {
+    let upper = single(c.to_uppercase()).unwrap_or(c);
+    single(upper.to_lowercase()).unwrap_or(c)
+}
+
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let c = chars.next()?;
+    if chars.next().is_some() { None } else { Some(c) }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharClass {
+    pub negated: bool,
+    pub items: Vec<ClassItem>,
+    pub case_insensitive: bool,
+}
+
+impl CharClass {
+    pub fn new(items: Vec<ClassItem>, negated: bool) -> Self {
+        Self { negated, items, case_insensitive: false }
+    }
+
+    pub fn with_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    pub fn matches(&self, c: char) -> bool {
+        let found = if self.case_insensitive {
+            // a range like `A-Z` only lists one case, so try both
+            let upper = single(c.to_uppercase()).unwrap_or(c);
+            self.contains(c) || self.contains(fold_case(c)) || self.contains(upper)
+        }
+        else {
+            self.contains(c)
+        };
+        found != self.negated
+    }
+
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match item {
+            ClassItem::Char(x) => *x == c,
+            ClassItem::Range(lo, hi) => *lo <= c && c <= *hi,
+            ClassItem::Digit => c.is_ascii_digit(),
+            ClassItem::Word => c.is_alphanumeric() || c == '_',
+            ClassItem::Space => c.is_whitespace(),
+        })
+    }
+}
+
+/// The compiled form of a pattern. Built once by `Parser::parse_regex` and
+/// walked by the matcher for every haystack.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Empty,
+    Literal(String),
+    /// case-insensitive literal, stored already case folded
+    FoldedLiteral(String),
+    Dot,
+    Class(CharClass),
+    StartAnchor,
+    EndAnchor,
+    /// capturing group, numbered from 1 by the position of its `(`
+    Group { index: usize, node: Box<Node> },
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    /// greedy repetition, `max == None` means unbounded
+    Repeat { node: Box<Node>, min: u32, max: Option<u32> },
+    Backreference(usize),
+    /// back reference compared case-insensitively
+    FoldedBackreference(usize),
+}
+
+impl Node {
+    /// Renumbers every group and back reference by `by`, used when several
+    /// separately parsed patterns are joined into one alternation.
+    pub(crate) fn shift_groups(&mut self, by: usize) {
+        match self {
+            Node::Group { index, node } => {
+                *index += by;
+                node.shift_groups(by);
+            }
+            Node::Backreference(index) | Node::FoldedBackreference(index) => *index += by,
+            Node::Concat(nodes) | Node::Alternation(nodes) => {
+                nodes.iter_mut().for_each(|node| node.shift_groups(by));
+            }
+            Node::Repeat { node, .. } => node.shift_groups(by),
+            Node::Empty | Node::Literal(_) | Node::FoldedLiteral(_) | Node::Dot | Node::Class(_) | Node::StartAnchor | Node::EndAnchor => {}
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::cell::Cell;
+
+use crate::ast::{fold_case, Node};
+use crate::error::MatchError;
+
+/// Capture slots indexed by group number, slot 0 being the whole match.
+pub(crate) type Slots = Vec<Option<(usize, usize)>>;
+
+/// Decodes the unit at the start of `bytes`: a char and its length, or
+/// `None` for a byte that does not start a valid UTF-8 sequence. Without
+/// `utf8` every byte is a unit on its own and only ASCII bytes are chars.
+pub(crate) fn decode_unit(bytes: &[u8], utf8: bool) -> Option<(Option<char>, usize)> {
+    let &first = bytes.first()?;
+    if first < 0x80 {
+        return Some((Some(first as char), 1));
+    }
+    if !utf8 {
+        return Some((None, 1));
+    }
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Some((None, 1)),
+    };
+    match bytes.get(..len).and_then(|bytes| std::str::from_utf8(bytes).ok()) {
+        Some(decoded) => Some((decoded.chars().next(), len)),
+        None => Some((None, 1)),
+    }
+}
+
+/// Whether `^` matches at `at`: at the start of the text, and in
+/// multi-line mode after every `\n`.
+pub(crate) fn at_line_start(text: &[u8], at: usize, multi_line: bool) -> bool {
+    at == 0 || (multi_line && text[at - 1] == b'\n')
+}
+
+/// Whether `$` matches at `at`: at the end of the text, and in multi-line
+/// mode before every `\n` or `\r\n`.
+pub(crate) fn at_line_end(text: &[u8], at: usize, multi_line: bool) -> bool {
+    if !multi_line {
+        return at == text.len();
+    }
+    let rest = &text[at..];
+    let rest = rest.strip_prefix(b"\r").unwrap_or(rest);
+    rest.is_empty() || rest[0] == b'\n'
+}
+
+/// Recursive backtracking matcher over the AST. Positions are byte offsets
+/// into `text`; in UTF-8 mode they sit on a char boundary wherever the text
+/// is valid UTF-8. In multi-line mode no match spans a `\n`, and anchors
+/// also match at the start and end (before any `\r`) of every line.
+pub(crate) struct Backtracker<'t> {
+    text: &'t [u8],
+    utf8: bool,
+    multi_line: bool,
+    limits: Limits,
+    // where the current match attempt started, and what the search used up
+    attempt: Cell<usize>,
+    steps: Cell<usize>,
+    depth: Cell<usize>,
+    exceeded: Cell<Option<MatchError>>,
+}
+
+/// How far a search may go: the steps it may take over all its attempts
+/// (in multi-line mode, on one line), and how deeply one attempt may nest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Limits {
+    pub(crate) steps: usize,
+    pub(crate) depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { steps: DEFAULT_STEP_BUDGET, depth: DEFAULT_RECURSION_LIMIT }
+    }
+}
+
+// room for the quadratic work of a pattern like `(\w+) \1` on a long word,
+// while a catastrophic one gives up within a fraction of a second
+pub(crate) const DEFAULT_STEP_BUDGET: usize = 10_000_000;
+
+// low enough to stay within the 2 MiB stack of a spawned thread, even in
+// a debug build
+pub(crate) const DEFAULT_RECURSION_LIMIT: usize = 1_000;
+
+impl<'t> Backtracker<'t> {
+    pub(crate) fn new(text: &'t [u8], utf8: bool) -> Self {
+        Self {
+            text,
+            utf8,
+            multi_line: false,
+            limits: Limits::default(),
+            attempt: Cell::new(0),
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            exceeded: Cell::new(None),
+        }
+    }
+
+    pub(crate) fn multi_line(mut self, yes: bool) -> Self {
+        self.multi_line = yes;
+        self
+    }
+
+    pub(crate) fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Tries every start position from `start` on and returns the slots of
+    /// the leftmost match, or the error of the attempt that went over the
+    /// limits.
+    pub(crate) fn search(&self, node: &Node, mut start: usize, group_count: usize) -> Result<Option<Slots>, MatchError> {
+        self.steps.set(0);
+        loop {
+            // no match spans lines, so each line is a search of its own and
+            // gets the whole budget whether or not the lines were searched
+            // together
+            if at_line_start(self.text, start, self.multi_line) {
+                self.steps.set(0);
+            }
+            if let Some(slots) = self.match_at(node, start, group_count)? {
+                return Ok(Some(slots));
+            }
+            match self.step(start) {
+                Some(next) => start = next,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The position one unit after `at`, or `None` at the end of the text.
+    pub(crate) fn step(&self, at: usize) -> Option<usize> {
+        decode_unit(&self.text[at..], self.utf8).map(|(_, len)| at + len)
+    }
+
+    // tries to match `node` starting exactly at `start`, returning the
+    // capture slots of the first (leftmost, greedy) match
+    fn match_at(&self, node: &Node, start: usize, group_count: usize) -> Result<Option<Slots>, MatchError> {
+        let mut slots: Slots = vec![None; group_count + 1];
+        let mut end = None;
+        self.attempt.set(start);
+        let matched = self.match_node(node, start, &mut slots, &mut |at, _| {
+            end = Some(at);
+            true
+        });
+        if let Some(err) = self.exceeded.take() {
+            return Err(err);
+        }
+        if !matched {
+            return Ok(None);
+        }
+        slots[0] = Some((start, end.unwrap()));
+        Ok(Some(slots))
+    }
+
+    // counts a step, failing the whole attempt once over the budget
+    fn take_step(&self) -> bool {
+        if self.exceeded.get().is_some() {
+            return false;
+        }
+        self.steps.set(self.steps.get() + 1);
+        if self.steps.get() > self.limits.steps {
+            self.exceeded.set(Some(MatchError::BudgetExceeded { offset: self.attempt.get() }));
+            return false;
+        }
+        true
+    }
+
+    // counts a step one level deeper, failing the whole attempt once over
+    // a limit
+    fn enter(&self) -> bool {
+        if !self.take_step() {
+            return false;
+        }
+        if self.depth.get() == self.limits.depth {
+            self.exceeded.set(Some(MatchError::RecursionLimitExceeded { offset: self.attempt.get() }));
+            return false;
+        }
+        self.depth.set(self.depth.get() + 1);
+        true
+    }
+
+    fn next_unit(&self, at: usize) -> Option<(Option<char>, usize)> {
+        decode_unit(&self.text[at..], self.utf8)
+    }
+
+    fn match_node(
+        &self,
+        node: &Node,
+        at: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        if !self.enter() {
+            return false;
+        }
+        let matched = self.match_step(node, at, slots, next);
+        self.depth.set(self.depth.get() - 1);
+        matched
+    }
+
+    fn match_step(
+        &self,
+        node: &Node,
+        at: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        match node {
+            Node::Empty => next(at, slots),
+            Node::Literal(literal) => {
+                if self.text[at..].starts_with(literal.as_bytes()) && !self.crosses_line(literal) {
+                    next(at + literal.len(), slots)
+                }
+                else {
+                    false
+                }
+            }
+            Node::FoldedLiteral(literal) if !self.crosses_line(literal) => match self.folded_prefix(at, literal) {
+                Some(end) => next(end, slots),
+                None => false,
+            },
+            Node::FoldedLiteral(_) => false,
+            // in byte mode `.` takes any byte but a newline, UTF-8 or not
+            Node::Dot => match self.next_unit(at) {
+                Some((c, len)) if c.map_or(!self.utf8, |c| c != '\n') => next(at + len, slots),
+                _ => false,
+            },
+            Node::Class(class) => match self.next_unit(at) {
+                Some((Some('\n'), _)) if self.multi_line => false,
+                Some((Some(c), len)) if class.matches(c) => next(at + len, slots),
+                Some((None, len)) if class.negated && !self.utf8 => next(at + len, slots),
+                _ => false,
+            },
+            Node::StartAnchor => at_line_start(self.text, at, self.multi_line) && next(at, slots),
+            Node::EndAnchor => at_line_end(self.text, at, self.multi_line) && next(at, slots),
+            Node::Group { index, node } => {
+                let index = *index;
+                self.match_node(node, at, slots, &mut |end, slots| {
+                    let saved = slots[index];
+                    slots[index] = Some((at, end));
+                    if next(end, slots) {
+                        return true;
+                    }
+                    slots[index] = saved;
+                    false
+                })
+            }
+            Node::Concat(nodes) => self.match_sequence(nodes, at, slots, next),
+            Node::Alternation(branches) => {
+                branches.iter().any(|branch| self.match_node(branch, at, slots, next))
+            }
+            Node::Repeat { node, min, max } if takes_one_unit(node) => {
+                self.match_units(node, *min, *max, at, slots, next)
+            }
+            Node::Repeat { node, min, max } => self.match_repeat(node, *min, *max, 0, at, slots, next),
+            Node::Backreference(index) => match slots[*index] {
+                Some((start, end)) => {
+                    let captured = &self.text[start..end];
+                    self.text[at..].starts_with(captured) && next(at + captured.len(), slots)
+                }
+                None => false,
+            },
+            Node::FoldedBackreference(index) => match slots[*index] {
+                Some((start, end)) => match self.folded_repeat(at, start, end) {
+                    Some(end) => next(end, slots),
+                    None => false,
+                },
+                None => false,
+            },
+        }
+    }
+
+    // only a pattern written with a newline in it can have one in a literal
+    fn crosses_line(&self, literal: &str) -> bool {
+        self.multi_line && literal.contains('\n')
+    }
+
+    // if the text at `at` equals the already folded `literal` up to case,
+    // returns where that text ends (folding may change byte lengths); in
+    // byte mode only ASCII letters are folded
+    fn folded_prefix(&self, at: usize, literal: &str) -> Option<usize> {
+        let mut end = at;
+        for expected in literal.chars() {
+            if !self.utf8 && !expected.is_ascii() {
+                let mut encoded = [0; 4];
+                let encoded = expected.encode_utf8(&mut encoded).as_bytes();
+                if !self.text[end..].starts_with(encoded) {
+                    return None;
+                }
+                end += encoded.len();
+                continue;
+            }
+            match self.next_unit(end)? {
+                (Some(c), len) if fold_case(c) == expected => end += len,
+                _ => return None,
+            }
+        }
+        Some(end)
+    }
+
+    // like `folded_prefix` for the text captured at `start..end`; bytes that
+    // are not chars have to repeat exactly
+    fn folded_repeat(&self, at: usize, start: usize, end: usize) -> Option<usize> {
+        let mut captured = start;
+        let mut position = at;
+        while captured < end {
+            let (expected, expected_len) = decode_unit(&self.text[captured..end], self.utf8)?;
+            let (found, len) = self.next_unit(position)?;
+            let same = match (expected, found) {
+                (Some(expected), Some(found)) => fold_case(expected) == fold_case(found),
+                (None, None) => self.text[captured] == self.text[position],
+                _ => false,
+            };
+            if !same {
+                return None;
+            }
+            captured += expected_len;
+            position += len;
+        }
+        Some(position)
+    }
+
+    fn match_sequence(
+        &self,
+        nodes: &[Node],
+        at: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        match nodes.split_first() {
+            None => next(at, slots),
+            Some((first, rest)) => self.match_node(first, at, slots, &mut |at, slots| {
+                self.match_sequence(rest, at, slots, next)
+            }),
+        }
+    }
+
+    // a repetition of a single char, class or `.`, like `\w+`: where each
+    // count of them ends is found with a loop rather than a level of
+    // recursion each, so long runs do not nest deeply
+    fn match_units(
+        &self,
+        node: &Node,
+        min: u32,
+        max: Option<u32>,
+        at: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        let mut ends = vec![at];
+        while max.map_or(true, |max| ends.len() <= max as usize) {
+            if !self.take_step() {
+                return false;
+            }
+            let mut end = None;
+            self.match_step(node, ends[ends.len() - 1], slots, &mut |unit_end, _| {
+                end = Some(unit_end);
+                true
+            });
+            match end {
+                Some(end) => ends.push(end),
+                None => break,
+            }
+        }
+        // greedy: the most units first
+        for &end in ends.iter().skip(min as usize).rev() {
+            if !self.take_step() {
+                return false;
+            }
+            if next(end, slots) {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn match_repeat(
+        &self,
+        node: &Node,
+        min: u32,
+        max: Option<u32>,
+        count: u32,
+        at: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        // greedy: try one more repetition before handing over to the rest
+        if max.map_or(true, |max| count < max) {
+            let more = self.match_node(node, at, slots, &mut |end, slots| {
+                // an empty iteration past the minimum can only loop forever
+                if end == at && count >= min {
+                    return false;
+                }
+                self.match_repeat(node, min, max, count + 1, end, slots, next)
+            });
+            if more {
+                return true;
+            }
+        }
+        count >= min && next(at, slots)
+    }
+}
+
+// whether `node` can match in at most one way at a position, always taking
+// something, so that a repetition of it never has to revisit a choice
+fn takes_one_unit(node: &Node) -> bool {
+    match node {
+        Node::Dot | Node::Class(_) => true,
+        Node::Literal(literal) | Node::FoldedLiteral(literal) => literal.chars().count() == 1,
+        _ => false,
+    }
+}
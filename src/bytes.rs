@@ -0,0 +1,350 @@
+//! The same regexes matched against `&[u8]` haystacks that need not be
+//! valid UTF-8.
+//!
+//! In UTF-8 mode (the default) codepoints are decoded on the fly, and a byte
+//! that does not start a valid sequence is only ever matched by a literal.
+//! With `RegexBuilder::utf8(false)` every byte is one character instead:
+//! `.` matches any byte but a newline, negated classes match non-ASCII
+//! bytes, and case-insensitive matching only folds ASCII letters.
+//!
+//! `RegexBuilder::multi_line` makes a haystack of many lines searchable as
+//! a whole: matches stay within one line and `^`/`$` anchor to every line.
+
+use std::fmt;
+use std::ops::{Index, Range};
+use std::str::FromStr;
+
+use crate::ast::Node;
+use crate::backtrack::{decode_unit, Slots};
+use crate::error::{Error, MatchError};
+
+/// A compiled pattern for byte haystacks. As with `crate::Regex`, only the
+/// `try_` methods report going over the backtracking limits.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    regex: crate::regex::Regex,
+    utf8: bool,
+    multi_line: bool,
+}
+
+/// Configures how patterns are compiled for byte haystacks.
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    builder: crate::regex::RegexBuilder,
+    utf8: bool,
+    multi_line: bool,
+}
+
+impl Default for RegexBuilder {
+    fn default() -> Self {
+        Self { builder: crate::regex::RegexBuilder::new(), utf8: true, multi_line: false }
+    }
+}
+
+impl RegexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches letters regardless of case, as if the pattern started with
+    /// `(?i)`.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.builder.case_insensitive(yes);
+        self
+    }
+
+    /// Decodes the haystack as UTF-8 (the default) rather than matching it
+    /// byte by byte.
+    pub fn utf8(&mut self, yes: bool) -> &mut Self {
+        self.utf8 = yes;
+        self
+    }
+
+    /// See `crate::RegexBuilder::size_limit`.
+    pub fn size_limit(&mut self, insts: usize) -> &mut Self {
+        self.builder.size_limit(insts);
+        self
+    }
+
+    /// See `crate::RegexBuilder::step_budget`.
+    pub fn step_budget(&mut self, steps: usize) -> &mut Self {
+        self.builder.step_budget(steps);
+        self
+    }
+
+    /// See `crate::RegexBuilder::recursion_limit`.
+    pub fn recursion_limit(&mut self, depth: usize) -> &mut Self {
+        self.builder.recursion_limit(depth);
+        self
+    }
+
+    /// Treats the haystack as `\n`-terminated lines: no match spans a
+    /// `\n`, `^` also matches after one and `$` before one (or before a
+    /// `\r\n`).
+    pub fn multi_line(&mut self, yes: bool) -> &mut Self {
+        self.multi_line = yes;
+        self
+    }
+
+    pub fn build(&self, pattern: &str) -> Result<Regex, Error> {
+        self.build_many([pattern])
+    }
+
+    /// See `crate::RegexBuilder::build_many`.
+    pub fn build_many<I, S>(&self, patterns: I) -> Result<Regex, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let regex = self.builder.build_many(patterns)?;
+        Ok(Regex { regex, utf8: self.utf8, multi_line: self.multi_line })
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, Error> {
+        RegexBuilder::new().build(pattern)
+    }
+
+    /// Shorthand for `RegexBuilder::new().build_many(patterns)`.
+    pub fn new_many<I, S>(patterns: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        RegexBuilder::new().build_many(patterns)
+    }
+
+    pub fn is_match(&self, haystack: &[u8]) -> bool {
+        self.try_is_match(haystack).unwrap_or(false)
+    }
+
+    pub fn try_is_match(&self, haystack: &[u8]) -> Result<bool, MatchError> {
+        Ok(self.try_shortest_match_at(haystack, 0)?.is_some())
+    }
+
+    /// Returns where a match starting at `start` or later ends, as soon as
+    /// one is known to exist; usually the match that ends first, which need
+    /// not be the leftmost one. Cheaper than `find_at` when only whether
+    /// (or, in multi-line mode, on which line) there is a match matters.
+    pub fn shortest_match_at(&self, haystack: &[u8], start: usize) -> Option<usize> {
+        self.try_shortest_match_at(haystack, start).unwrap_or(None)
+    }
+
+    pub fn try_shortest_match_at(&self, haystack: &[u8], start: usize) -> Result<Option<usize>, MatchError> {
+        if start > haystack.len() {
+            return Ok(None);
+        }
+        self.regex.match_end(haystack, start, self.utf8, self.multi_line)
+    }
+
+    /// Returns the leftmost match in `haystack`, if any.
+    pub fn find<'h>(&self, haystack: &'h [u8]) -> Option<Match<'h>> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Like `find`, but starts looking at byte offset `start`. Anchors still
+    /// see the whole haystack, so `^` can only match at offset 0 (or after a
+    /// newline in multi-line mode).
+    pub fn find_at<'h>(&self, haystack: &'h [u8], start: usize) -> Option<Match<'h>> {
+        self.try_find_at(haystack, start).unwrap_or(None)
+    }
+
+    pub fn try_find_at<'h>(&self, haystack: &'h [u8], start: usize) -> Result<Option<Match<'h>>, MatchError> {
+        let Some(slots) = self.search_at(haystack, start)? else {
+            return Ok(None);
+        };
+        let (start, end) = slots[0].unwrap();
+        Ok(Some(Match::new(haystack, start, end)))
+    }
+
+    /// Returns the leftmost match along with the span of every capturing
+    /// group.
+    pub fn captures<'h>(&self, haystack: &'h [u8]) -> Option<Captures<'h>> {
+        self.captures_at(haystack, 0)
+    }
+
+    pub fn captures_at<'h>(&self, haystack: &'h [u8], start: usize) -> Option<Captures<'h>> {
+        self.try_captures_at(haystack, start).unwrap_or(None)
+    }
+
+    pub fn try_captures_at<'h>(&self, haystack: &'h [u8], start: usize) -> Result<Option<Captures<'h>>, MatchError> {
+        Ok(self.search_at(haystack, start)?.map(|slots| Captures { haystack, slots }))
+    }
+
+    /// Iterates over successive non-overlapping matches, skipping an empty
+    /// match right where the previous match ended.
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h [u8]) -> Matches<'r, 'h> {
+        Matches { captures: self.captures_iter(haystack) }
+    }
+
+    pub fn captures_iter<'r, 'h>(&'r self, haystack: &'h [u8]) -> CapturesMatches<'r, 'h> {
+        CapturesMatches { regex: self, haystack, at: 0, last_end: None }
+    }
+
+    fn search_at(&self, haystack: &[u8], start: usize) -> Result<Option<Slots>, MatchError> {
+        if start > haystack.len() {
+            return Ok(None);
+        }
+        self.regex.search_bytes(haystack, start, self.utf8, self.multi_line)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    pub fn ast(&self) -> &Node {
+        self.regex.ast()
+    }
+
+    /// Number of capturing groups, not counting the implicit whole match.
+    pub fn group_count(&self) -> usize {
+        self.regex.group_count()
+    }
+
+    /// Whether haystacks are decoded as UTF-8.
+    pub fn is_utf8(&self) -> bool {
+        self.utf8
+    }
+
+    pub fn is_multi_line(&self) -> bool {
+        self.multi_line
+    }
+}
+
+impl FromStr for Regex {
+    type Err = Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Error> {
+        Self::new(pattern)
+    }
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single match: byte offsets into the haystack it was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    fn new(haystack: &'h [u8], start: usize, end: usize) -> Self {
+        Self { haystack, start, end }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn as_bytes(&self) -> &'h [u8] {
+        &self.haystack[self.start..self.end]
+    }
+}
+
+/// The spans of all capturing groups of a match. Group 0 is the whole
+/// match; a group that did not take part in the match is `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captures<'h> {
+    haystack: &'h [u8],
+    slots: Slots,
+}
+
+impl<'h> Captures<'h> {
+    pub fn get(&self, index: usize) -> Option<Match<'h>> {
+        let (start, end) = (*self.slots.get(index)?)?;
+        Some(Match::new(self.haystack, start, end))
+    }
+
+    /// The whole match, same as `get(0)`.
+    pub fn get_match(&self) -> Match<'h> {
+        self.get(0).unwrap()
+    }
+
+    /// Number of groups including group 0.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match<'h>>> + '_ {
+        (0..self.slots.len()).map(|index| self.get(index))
+    }
+}
+
+impl Index<usize> for Captures<'_> {
+    type Output = [u8];
+
+    /// Panics if the group does not exist or did not participate.
+    fn index(&self, index: usize) -> &[u8] {
+        self.get(index)
+            .unwrap_or_else(|| panic!("no group at index {}", index))
+            .as_bytes()
+    }
+}
+
+pub struct CapturesMatches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h [u8],
+    at: usize,
+    last_end: Option<usize>,
+}
+
+impl<'h> Iterator for CapturesMatches<'_, 'h> {
+    type Item = Captures<'h>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.at <= self.haystack.len() {
+            let captures = self.regex.captures_at(self.haystack, self.at)?;
+            let found = captures.get_match();
+            if found.is_empty() && Some(found.end()) == self.last_end {
+                // step over one unit so we cannot report the same spot twice
+                let end = found.end();
+                self.at = decode_unit(&self.haystack[end..], self.regex.utf8)
+                    .map_or(self.haystack.len() + 1, |(_, len)| end + len);
+                continue;
+            }
+            self.at = found.end();
+            self.last_end = Some(found.end());
+            return Some(captures);
+        }
+        None
+    }
+}
+
+pub struct Matches<'r, 'h> {
+    captures: CapturesMatches<'r, 'h>,
+}
+
+impl<'h> Iterator for Matches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.captures.next().map(|captures| captures.get_match())
+    }
+}
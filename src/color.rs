@@ -0,0 +1,115 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    Always,
+    #[default]
+    Never,
+    Auto,
+}
+
+impl FromStr for ColorChoice {
+    type Err = ();
+
+    // accepts the same spellings as GNU grep
+    fn from_str(value: &str) -> Result<Self, ()> {
+        match value {
+            "always" | "yes" | "force" => Ok(ColorChoice::Always),
+            "never" | "no" | "none" => Ok(ColorChoice::Never),
+            "auto" | "tty" | "if-tty" => Ok(ColorChoice::Auto),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// `auto` colors only when stdout is a terminal and `NO_COLOR` is unset.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                !no_color && io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+/// SGR sequences for each part of the output, GNU grep's defaults
+/// overridden by `GREP_COLORS` (e.g. `ms=01;32:fn=34:se=`).
+#[derive(Debug, Clone)]
+pub struct Colors {
+    pub selected_match: String,
+    pub context_match: String,
+    pub filename: String,
+    pub line_number: String,
+    pub byte_offset: String,
+    pub separator: String,
+    // `ne` drops the erase-to-end-of-line sequence
+    erase_line: bool,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            selected_match: "01;31".to_string(),
+            context_match: "01;31".to_string(),
+            filename: "35".to_string(),
+            line_number: "32".to_string(),
+            byte_offset: "32".to_string(),
+            separator: "36".to_string(),
+            erase_line: true,
+        }
+    }
+}
+
+impl Colors {
+    pub fn from_env() -> Self {
+        let mut colors = Self::default();
+        if let Ok(spec) = env::var("GREP_COLORS") {
+            colors.apply(&spec);
+        }
+        colors
+    }
+
+    // unknown capabilities are ignored, like GNU grep does
+    fn apply(&mut self, spec: &str) {
+        for entry in spec.split(':') {
+            let (name, value) = entry.split_once('=').unwrap_or((entry, ""));
+            let value = value.to_string();
+            match name {
+                "mt" => {
+                    self.selected_match = value.clone();
+                    self.context_match = value;
+                }
+                "ms" => self.selected_match = value,
+                "mc" => self.context_match = value,
+                "fn" => self.filename = value,
+                "ln" => self.line_number = value,
+                "bn" => self.byte_offset = value,
+                "se" => self.separator = value,
+                "ne" => self.erase_line = false,
+                _ => {}
+            }
+        }
+    }
+
+    /// Wraps `text` in the given SGR sequence; an empty one leaves it plain.
+    pub fn paint(&self, sgr: &str, text: &str) -> String {
+        String::from_utf8(self.paint_bytes(sgr, text.as_bytes())).unwrap()
+    }
+
+    pub fn paint_bytes(&self, sgr: &str, text: &[u8]) -> Vec<u8> {
+        if sgr.is_empty() {
+            return text.to_vec();
+        }
+        let erase = if self.erase_line { "\x1b[K" } else { "" };
+        let mut painted = format!("\x1b[{}m{}", sgr, erase).into_bytes();
+        painted.extend_from_slice(text);
+        painted.extend_from_slice(format!("\x1b[m{}", erase).as_bytes());
+        painted
+    }
+}
@@ -0,0 +1,257 @@
+//! Lazy DFA behind `Dfa::match_end`, which finds where some match ends
+//! without working out where it starts. Each state is the set of NFA
+//! threads alive at a position, built the first time a search reaches it
+//! and cached along with its transitions on ASCII bytes. Once the cache is
+//! full it is cleared, and the search goes on from the state it is in.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::fold_case;
+use crate::backtrack::{at_line_end, at_line_start, decode_unit};
+use crate::nfa::{Inst, Nfa, SparseSet};
+use crate::pool::Pool;
+
+// enough for the patterns people grep with, at 1 KiB of transitions each
+const MAX_STATES: usize = 1024;
+
+const UNKNOWN: u32 = u32::MAX;
+
+// `$` can only be settled knowing what follows a position, so the
+// threads waiting on one are kept in a state until the next step
+struct State {
+    pcs: Box<[usize]>,
+    // whether `^` matches here
+    line_start: bool,
+    matched: bool,
+    // whether the state holds a match if `$` matches here
+    matched_at_line_end: bool,
+}
+
+struct Cache {
+    multi_line: bool,
+    states: Vec<State>,
+    ids: HashMap<(Box<[usize]>, bool), u32>,
+    // the state a search starts in, without and with `^` matching there
+    starts: [Option<u32>; 2],
+    // how many times the cache was cleared, so that a search knows when
+    // the ids it holds are gone
+    clears: usize,
+    // 256 per state: the ASCII byte, plus 128 if `$` matches before it
+    transitions: Vec<u32>,
+    seen: SparseSet,
+    stack: Vec<usize>,
+}
+
+/// The lazy DFA of a regex, with a cache for each search running at a time.
+#[derive(Clone)]
+pub(crate) struct Dfa {
+    // in byte mode a case-insensitive non-ASCII char spans several units,
+    // which the states cannot follow
+    wide: bool,
+    caches: Pool<Cache>,
+}
+
+impl Dfa {
+    pub(crate) fn new(nfa: Option<&Nfa>) -> Self {
+        let wide = nfa.is_some_and(|nfa| nfa.insts.iter().any(|inst| matches!(inst, Inst::Folded(c) if !c.is_ascii())));
+        Self { wide, caches: Pool::new() }
+    }
+
+    /// Whether `match_end` can search in this mode; the Pike VM has to
+    /// otherwise.
+    pub(crate) fn supports(&self, utf8: bool) -> bool {
+        utf8 || !self.wide
+    }
+
+    /// Where a match of `nfa` starting at `start` or later ends, as soon
+    /// as one is found: with the DFA the match that ends first.
+    pub(crate) fn match_end(&self, nfa: &Nfa, text: &[u8], start: usize, utf8: bool, multi_line: bool) -> Option<usize> {
+        let fresh = |cache: Option<Cache>| match cache {
+            Some(cache) if cache.multi_line == multi_line => cache,
+            _ => Cache::new(nfa, multi_line),
+        };
+        self.caches.with(fresh, |cache| cache.match_end(nfa, text, start, utf8))
+    }
+}
+
+impl fmt::Debug for Dfa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dfa").finish_non_exhaustive()
+    }
+}
+
+impl Cache {
+    fn new(nfa: &Nfa, multi_line: bool) -> Self {
+        Self {
+            multi_line,
+            states: Vec::new(),
+            ids: HashMap::new(),
+            starts: [None; 2],
+            clears: 0,
+            transitions: Vec::new(),
+            seen: SparseSet::new(nfa.insts.len()),
+            stack: Vec::new(),
+        }
+    }
+
+    fn match_end(&mut self, nfa: &Nfa, text: &[u8], start: usize, utf8: bool) -> Option<usize> {
+        let line_start = at_line_start(text, start, self.multi_line);
+        let mut state = match self.starts[line_start as usize] {
+            Some(state) => state,
+            None => {
+                let state = self.intern(nfa, vec![0], line_start);
+                self.starts[line_start as usize] = Some(state);
+                state
+            }
+        };
+        let mut at = start;
+        loop {
+            let current = &self.states[state as usize];
+            if current.matched {
+                return Some(at);
+            }
+            // the common case of an ASCII byte, without decoding a unit
+            if let Some(&byte) = text.get(at).filter(|byte| byte.is_ascii()) {
+                let line_end = self.multi_line
+                    && match byte {
+                        b'\n' => true,
+                        b'\r' => matches!(text.get(at + 1), None | Some(b'\n')),
+                        _ => false,
+                    };
+                if line_end && current.matched_at_line_end {
+                    return Some(at);
+                }
+                let index = state as usize * 256 + byte as usize + if line_end { 128 } else { 0 };
+                state = match self.transitions[index] {
+                    UNKNOWN => {
+                        let clears = self.clears;
+                        let next = self.step(nfa, state, line_end, &text[at..at + 1], Some(byte as char), utf8);
+                        // a cleared cache no longer has the state it came from
+                        if self.clears == clears {
+                            self.transitions[index] = next;
+                        }
+                        next
+                    }
+                    next => next,
+                };
+                at += 1;
+                continue;
+            }
+
+            let line_end = at_line_end(text, at, self.multi_line);
+            if line_end && current.matched_at_line_end {
+                return Some(at);
+            }
+            // other units are rare enough to go without a transition
+            let (c, len) = decode_unit(&text[at..], utf8)?;
+            state = self.step(nfa, state, line_end, &text[at..at + len], c, utf8);
+            at += len;
+        }
+    }
+
+    // the state after `unit` (decoded as `c`, if a char) from `state`
+    fn step(
+        &mut self,
+        nfa: &Nfa,
+        state: u32,
+        line_end: bool,
+        unit: &[u8],
+        c: Option<char>,
+        utf8: bool,
+    ) -> u32 {
+        let state = &self.states[state as usize];
+        let line_start = state.line_start;
+        let pcs = state.pcs.clone();
+        let resolved = self.closure(nfa, pcs.iter().copied(), line_start, Some(line_end));
+        let mut next = vec![0];
+        if !(self.multi_line && c == Some('\n')) {
+            next.extend(resolved.into_iter().filter_map(|pc| consume(nfa, pc, unit, c, utf8)));
+        }
+        let line_start = self.multi_line && unit == b"\n";
+        self.intern(nfa, next, line_start)
+    }
+
+    fn intern(&mut self, nfa: &Nfa, pcs: Vec<usize>, line_start: bool) -> u32 {
+        let pcs = self.closure(nfa, pcs.into_iter(), line_start, None).into_boxed_slice();
+        if let Some(&id) = self.ids.get(&(pcs.clone(), line_start)) {
+            return id;
+        }
+        if self.states.len() == MAX_STATES {
+            self.clear();
+        }
+        let is_match = |pc: &usize| matches!(nfa.insts[*pc], Inst::Match);
+        let matched = pcs.iter().any(is_match);
+        let matched_at_line_end = self.closure(nfa, pcs.iter().copied(), line_start, Some(true)).iter().any(is_match);
+        let id = self.states.len() as u32;
+        self.states.push(State { pcs: pcs.clone(), line_start, matched, matched_at_line_end });
+        self.ids.insert((pcs, line_start), id);
+        self.transitions.extend([UNKNOWN; 256]);
+        id
+    }
+
+    fn clear(&mut self) {
+        self.states.clear();
+        self.ids.clear();
+        self.transitions.clear();
+        self.starts = [None; 2];
+        self.clears += 1;
+    }
+
+    // follows everything that consumes nothing from `pcs`; with `line_end`
+    // unknown the threads waiting on `$` stay as they are
+    fn closure(
+        &mut self,
+        nfa: &Nfa,
+        pcs: impl Iterator<Item = usize>,
+        line_start: bool,
+        line_end: Option<bool>,
+    ) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.stack.extend(pcs);
+        while let Some(pc) = self.stack.pop() {
+            if !self.seen.insert(pc) {
+                continue;
+            }
+            match nfa.insts[pc] {
+                Inst::Jump(target) => self.stack.push(target),
+                Inst::Split(first, second) => self.stack.extend([first, second]),
+                // no empty iteration can make a match possible that was not
+                Inst::Save(_) | Inst::Progress(_) => self.stack.push(pc + 1),
+                Inst::StartAnchor => {
+                    if line_start {
+                        self.stack.push(pc + 1);
+                    }
+                }
+                Inst::EndAnchor => match line_end {
+                    Some(true) => self.stack.push(pc + 1),
+                    Some(false) => {}
+                    None => found.push(pc),
+                },
+                _ => found.push(pc),
+            }
+        }
+        self.seen.clear();
+        found.sort_unstable();
+        found
+    }
+}
+
+// where the thread at `pc` goes on after `unit`, if it takes it
+fn consume(nfa: &Nfa, pc: usize, unit: &[u8], c: Option<char>, utf8: bool) -> Option<usize> {
+    match &nfa.insts[pc] {
+        // a literal char is as many bytes as the unit it has to equal
+        Inst::Byte(_) => {
+            let bytes = nfa.insts.get(pc..pc + unit.len())?;
+            let equal = bytes.iter().zip(unit).all(|(inst, &byte)| matches!(inst, Inst::Byte(b) if *b == byte));
+            equal.then_some(pc + unit.len())
+        }
+        Inst::Folded(expected) => c.is_some_and(|c| fold_case(c) == *expected).then_some(pc + 1),
+        Inst::Dot => c.map_or(!utf8, |c| c != '\n').then_some(pc + 1),
+        Inst::Class(class) => match c {
+            Some(c) => class.matches(c).then_some(pc + 1),
+            None => (class.negated && !utf8).then_some(pc + 1),
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,77 @@
+use thiserror::Error;
+
+/// Everything that can go wrong while parsing a pattern. Each variant
+/// carries the character offset in the pattern where the problem starts.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("trailing backslash")]
+    TrailingBackslash { offset: usize },
+    #[error("unclosed character class")]
+    UnclosedCharClass { offset: usize },
+    #[error("unclosed parenthesis")]
+    UnclosedParenthesis { offset: usize },
+    #[error("unmatched ')'")]
+    UnmatchedParenthesis { offset: usize },
+    #[error("unclosed repetition")]
+    UnclosedRepetition { offset: usize },
+    #[error("invalid repetition \"{{{content}}}\"")]
+    InvalidRepetition { offset: usize, content: String },
+    #[error("invalid back reference \\{index}")]
+    InvalidBackreference { offset: usize, index: usize },
+    #[error("unknown group flag '{flag}'")]
+    UnknownFlag { offset: usize, flag: char },
+    /// The pattern as a whole compiles to more than `limit` instructions;
+    /// the offset is always 0.
+    #[error("pattern too large: compiles to more than {limit} instructions")]
+    TooLarge { offset: usize, limit: usize },
+}
+
+impl ParseError {
+    pub fn offset(&self) -> usize {
+        match self {
+            ParseError::TrailingBackslash { offset }
+            | ParseError::UnclosedCharClass { offset }
+            | ParseError::UnclosedParenthesis { offset }
+            | ParseError::UnmatchedParenthesis { offset }
+            | ParseError::UnclosedRepetition { offset }
+            | ParseError::InvalidRepetition { offset, .. }
+            | ParseError::InvalidBackreference { offset, .. }
+            | ParseError::UnknownFlag { offset, .. }
+            | ParseError::TooLarge { offset, .. } => *offset,
+        }
+    }
+}
+
+/// Why a glob could not be compiled; offsets are character offsets.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum GlobError {
+    #[error("unclosed character class")]
+    UnclosedClass { offset: usize },
+    #[error("unclosed alternates")]
+    UnclosedAlternates { offset: usize },
+}
+
+/// Why the backtracker, which only runs patterns with back references,
+/// gave up on a search. Each variant carries the byte offset in the
+/// haystack of the match attempt that went over the limit.
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq)]
+pub enum MatchError {
+    #[error("backtracking step budget exceeded")]
+    BudgetExceeded { offset: usize },
+    #[error("backtracking recursion limit exceeded")]
+    RecursionLimitExceeded { offset: usize },
+}
+
+impl MatchError {
+    pub fn offset(&self) -> usize {
+        match self {
+            MatchError::BudgetExceeded { offset } | MatchError::RecursionLimitExceeded { offset } => *offset,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum Error {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+}
@@ -0,0 +1,159 @@
+use crate::ast::{CharClass, ClassItem};
+use crate::error::GlobError;
+
+/// One piece of a compiled glob.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`: any char but `/`.
+    One,
+    /// `*`: any run of chars without a `/`.
+    Any,
+    /// `**/`: nothing, or any run of whole path components.
+    AnyDirs,
+    /// `**` at the end (of the glob or of an alternative): anything at all,
+    /// `/` included.
+    AnyPath,
+    Class(CharClass),
+    /// `{a,b}`: any one of the alternatives.
+    Alternates(Vec<Vec<Token>>),
+}
+
+/// A shell-style wildcard pattern matched against `/`-separated paths:
+/// `*` and `?` stay within one component, `**` spans components when it
+/// stands for a whole one, `[...]` is a class (negated with `!` or `^`)
+/// and `{a,b}` matches either alternative.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut index = 0;
+        let tokens = parse_tokens(&chars, &mut index, false)?;
+        Ok(Self { pattern: pattern.to_string(), tokens })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        let chars: Vec<char> = path.chars().collect();
+        match_tokens(&self.tokens, &chars)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+// parses tokens from `index` on; inside braces it stops at the `,` or `}`
+// that ends the current alternative
+fn parse_tokens(chars: &[char], index: &mut usize, in_braces: bool) -> Result<Vec<Token>, GlobError> {
+    let mut tokens = Vec::new();
+    while *index < chars.len() {
+        let c = chars[*index];
+        match c {
+            ',' | '}' if in_braces => return Ok(tokens),
+            '*' if chars.get(*index + 1) == Some(&'*') => {
+                let starts_component = *index == 0 || matches!(chars[*index - 1], '/' | '{' | ',');
+                *index += 2;
+                match chars.get(*index) {
+                    Some('/') if starts_component => {
+                        tokens.push(Token::AnyDirs);
+                        *index += 1;
+                    }
+                    None if starts_component => tokens.push(Token::AnyPath),
+                    Some(',' | '}') if starts_component && in_braces => tokens.push(Token::AnyPath),
+                    // `**` inside a component is just a `*`
+                    _ => tokens.push(Token::Any),
+                }
+                continue;
+            }
+            '*' => tokens.push(Token::Any),
+            '?' => tokens.push(Token::One),
+            '[' => {
+                let (class, end) = parse_class(chars, *index)?;
+                tokens.push(Token::Class(class));
+                *index = end;
+            }
+            '{' => {
+                let start = *index;
+                let mut alternates = Vec::new();
+                loop {
+                    *index += 1;
+                    alternates.push(parse_tokens(chars, index, true)?);
+                    match chars.get(*index) {
+                        Some(',') => continue,
+                        Some('}') => break,
+                        _ => return Err(GlobError::UnclosedAlternates { offset: start }),
+                    }
+                }
+                tokens.push(Token::Alternates(alternates));
+            }
+            '\\' => match chars.get(*index + 1) {
+                Some(&escaped) => {
+                    tokens.push(Token::Char(escaped));
+                    *index += 1;
+                }
+                None => tokens.push(Token::Char('\\')),
+            },
+            c => tokens.push(Token::Char(c)),
+        }
+        *index += 1;
+    }
+    Ok(tokens)
+}
+
+// parses the class opening at `start`, returning it with the index of its `]`;
+// a `]` right after the opening (or the negation) is a member
+fn parse_class(chars: &[char], start: usize) -> Result<(CharClass, usize), GlobError> {
+    let mut index = start + 1;
+    let negated = matches!(chars.get(index), Some('!') | Some('^'));
+    if negated {
+        index += 1;
+    }
+    let mut items = Vec::new();
+    let first = index;
+    loop {
+        let c = *chars.get(index).ok_or(GlobError::UnclosedClass { offset: start })?;
+        if c == ']' && index > first {
+            return Ok((CharClass::new(items, negated), index));
+        }
+        if chars.get(index + 1) == Some(&'-') && chars.get(index + 2).is_some_and(|&hi| hi != ']') {
+            items.push(ClassItem::Range(c, chars[index + 2]));
+            index += 3;
+        }
+        else {
+            items.push(ClassItem::Char(c));
+            index += 1;
+        }
+    }
+}
+
+fn match_tokens(tokens: &[Token], path: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return path.is_empty();
+    };
+    match token {
+        Token::Char(c) => path.first() == Some(c) && match_tokens(rest, &path[1..]),
+        Token::One => path.first().is_some_and(|&c| c != '/') && match_tokens(rest, &path[1..]),
+        Token::Class(class) => {
+            path.first().is_some_and(|&c| c != '/' && class.matches(c)) && match_tokens(rest, &path[1..])
+        }
+        Token::Any => {
+            let run = path.iter().take_while(|&&c| c != '/').count();
+            (0..=run).any(|len| match_tokens(rest, &path[len..]))
+        }
+        Token::AnyDirs => {
+            // only after a `/` can the rest start a new component
+            match_tokens(rest, path)
+                || (0..path.len()).any(|index| path[index] == '/' && match_tokens(rest, &path[index + 1..]))
+        }
+        Token::AnyPath => rest.is_empty() || (0..=path.len()).any(|index| match_tokens(rest, &path[index..])),
+        Token::Alternates(alternates) => alternates.iter().any(|alternate| {
+            let tokens: Vec<Token> = alternate.iter().chain(rest).cloned().collect();
+            match_tokens(&tokens, path)
+        }),
+    }
+}
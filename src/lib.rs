@@ -1 +1,14 @@
-pub mod parse;
\ No newline at end of file
+pub mod ast;
+mod backtrack;
+pub mod bytes;
+mod dfa;
+pub mod error;
+pub mod glob;
+pub mod literal;
+mod nfa;
+pub mod parse;
+mod pool;
+pub mod regex;
+
+pub use error::{Error, MatchError};
+pub use regex::{Captures, CapturesMatches, Match, Matches, Regex, RegexBuilder};
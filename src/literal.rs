@@ -0,0 +1,195 @@
+//! Required literals: strings one of which every match of a pattern has to
+//! contain. Searching for them with a fast substring search first lets the
+//! matcher skip haystacks (or, in multi-line mode, whole lines) that cannot
+//! match.
+
+use memchr::memmem::Finder;
+
+use crate::ast::Node;
+
+// bigger sets are not worth searching for one by one
+const MAX_LITERALS: usize = 16;
+
+// a longer literal is hardly more selective, only slower to build
+const MAX_LITERAL_LEN: usize = 64;
+
+/// Returns a set of non-empty strings such that every match of `node`
+/// contains at least one of them, or `None` when no useful set is found.
+/// Case-insensitive parts of a pattern never contribute literals.
+pub fn required_literals(node: &Node) -> Option<Vec<String>> {
+    if let Some(set) = exact(node) {
+        return usable(set);
+    }
+    match node {
+        Node::Group { node, .. } => required_literals(node),
+        Node::Repeat { node, min, .. } if *min > 0 => {
+            // the first iterations are required, as far as is worth building
+            let prefix = exact(node).and_then(|once| {
+                let mut set = vec![String::new()];
+                for _ in 0..*min {
+                    if set.iter().all(|literal| literal.len() >= MAX_LITERAL_LEN) {
+                        break;
+                    }
+                    set = product(&set, &once)?;
+                }
+                usable(set)
+            });
+            prefix.or_else(|| required_literals(node))
+        }
+        Node::Alternation(branches) => {
+            let mut set = Vec::new();
+            for branch in branches {
+                set.extend(required_literals(branch)?);
+            }
+            bounded(set)
+        }
+        Node::Concat(nodes) => {
+            // adjacent exact parts join into longer literals; any part
+            // with a required set of its own is a candidate too
+            let mut candidates = Vec::new();
+            let mut run = vec![String::new()];
+            for node in nodes {
+                match exact(node) {
+                    Some(set) => match product(&run, &set) {
+                        Some(joined) => run = joined,
+                        None => candidates.push(std::mem::replace(&mut run, set)),
+                    },
+                    None => {
+                        candidates.push(std::mem::replace(&mut run, vec![String::new()]));
+                        candidates.extend(required_literals(node));
+                    }
+                }
+            }
+            candidates.push(run);
+            candidates.into_iter().filter_map(usable).max_by_key(|set| {
+                let shortest = set.iter().map(String::len).min().unwrap_or(0);
+                (shortest, std::cmp::Reverse(set.len()))
+            })
+        }
+        _ => None,
+    }
+}
+
+// every string `node` can match, when there are few enough of them
+fn exact(node: &Node) -> Option<Vec<String>> {
+    match node {
+        Node::Empty => Some(vec![String::new()]),
+        Node::Literal(literal) => Some(vec![literal.clone()]),
+        Node::Group { node, .. } => exact(node),
+        Node::Concat(nodes) => {
+            nodes.iter().try_fold(vec![String::new()], |set, node| product(&set, &exact(node)?))
+        }
+        Node::Alternation(branches) => {
+            let mut set = Vec::new();
+            for branch in branches {
+                set.extend(exact(branch)?);
+            }
+            bounded(set)
+        }
+        Node::Repeat { node, min, max: Some(max) } if min == max => {
+            let once = exact(node)?;
+            let longest = once.iter().map(String::len).max().unwrap_or(0);
+            if longest * *min as usize > MAX_LITERAL_LEN {
+                return None;
+            }
+            (0..*min).try_fold(vec![String::new()], |set, _| product(&set, &once))
+        }
+        _ => None,
+    }
+}
+
+// each string of `a` followed by each of `b`
+fn product(a: &[String], b: &[String]) -> Option<Vec<String>> {
+    if a.len() * b.len() > MAX_LITERALS {
+        return None;
+    }
+    bounded(a.iter().flat_map(|a| b.iter().map(move |b| format!("{}{}", a, b))).collect())
+}
+
+fn bounded(mut set: Vec<String>) -> Option<Vec<String>> {
+    set.sort();
+    set.dedup();
+    (set.len() <= MAX_LITERALS).then_some(set)
+}
+
+// a set with an empty string in it requires nothing
+fn usable(set: Vec<String>) -> Option<Vec<String>> {
+    (!set.is_empty() && set.iter().all(|literal| !literal.is_empty())).then_some(set)
+}
+
+/// Substring searchers for the required literals of a pattern.
+#[derive(Debug, Clone)]
+pub(crate) struct Prefilter {
+    finders: Vec<Finder<'static>>,
+}
+
+impl Prefilter {
+    pub(crate) fn new(node: &Node) -> Option<Self> {
+        let literals = required_literals(node)?;
+        let finders = literals.iter().map(|literal| Finder::new(literal.as_bytes()).into_owned()).collect();
+        Some(Self { finders })
+    }
+
+    /// Finds the leftmost match at or after `start` with `search(from, to)`,
+    /// which has to look for a match starting at `from` or later and ending
+    /// by `to`, calling it only where a required literal shows up; its
+    /// first error ends the search. In multi-line mode matches stay within
+    /// a line, so only lines holding a literal are searched, one at a time.
+    pub(crate) fn search<T, E>(
+        &self,
+        haystack: &[u8],
+        start: usize,
+        multi_line: bool,
+        mut search: impl FnMut(usize, usize) -> Result<Option<T>, E>,
+    ) -> Result<Option<T>, E> {
+        let mut scanner = Scanner { prefilter: self, next: vec![None; self.finders.len()] };
+        if !multi_line {
+            if scanner.find(haystack, start).is_none() {
+                return Ok(None);
+            }
+            return search(start, haystack.len());
+        }
+        let mut at = start;
+        while let Some(found) = scanner.find(haystack, at) {
+            let line_start = memchr::memrchr(b'\n', &haystack[..found]).map_or(0, |index| index + 1);
+            let line_end = memchr::memchr(b'\n', &haystack[found..]).map_or(haystack.len(), |index| found + index);
+            if let Some(result) = search(at.max(line_start), line_end)? {
+                return Ok(Some(result));
+            }
+            at = line_end + 1;
+            if at > haystack.len() {
+                break;
+            }
+        }
+        Ok(None)
+    }
+}
+
+// remembers where each literal occurs next, so that moving on through a
+// haystack scans it only once per literal
+struct Scanner<'p> {
+    prefilter: &'p Prefilter,
+    // `Some(None)` once a literal does not occur any more
+    next: Vec<Option<Option<usize>>>,
+}
+
+impl Scanner<'_> {
+    // the leftmost occurrence of any literal at or after `at`
+    fn find(&mut self, haystack: &[u8], at: usize) -> Option<usize> {
+        let mut leftmost: Option<usize> = None;
+        for (finder, next) in self.prefilter.finders.iter().zip(&mut self.next) {
+            let position = match *next {
+                Some(position) if position.map_or(true, |position| position >= at) => position,
+                _ => {
+                    let position = finder.find(&haystack[at..]).map(|index| at + index);
+                    *next = Some(position);
+                    position
+                }
+            };
+            if let Some(position) = position {
+                leftmost = Some(leftmost.map_or(position, |leftmost| leftmost.min(position)));
+            }
+        }
+        leftmost
+    }
+}
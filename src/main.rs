@@ -1,120 +1,134 @@
-mod parse;
+mod args;
+mod color;
+mod parallel;
+mod printer;
+mod search;
+mod walk;
+
+use args::{Args, Command};
+use color::Colors;
+use search::{display_name, Searcher, Status, TrackedWriter};
+use walk::PathIterator;
+use codecrafters_grep::bytes::RegexBuilder;
+use codecrafters_grep::Error;
 use std::env;
 use std::process;
 use std::io;
-use env_logger;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::thread;
 use std::collections::VecDeque;
-use std::path::Path;
-use std::fs;
 
 
 // Usage: echo <input_text> | your_program.sh -E <pattern>
 // for full debug logs, set the RUST_LOG environment variable to "debug": RUST_LOG=codecrafters_grep=debug
-struct PathIterator {
-    queue: VecDeque<String>,
-    is_recursive: bool,
-}
 
-impl PathIterator {
-    fn new(args: VecDeque<String>, is_recursive: bool) -> Self {
-        Self { queue: args, is_recursive }
+// prints the error with a caret under the offending character of the
+// pattern; offsets count multiple patterns as if joined by newlines
+fn report_pattern_error(patterns: &[String], err: &Error) {
+    match err {
+        Error::Parse(parse_error) => {
+            eprintln!("grep: {}", parse_error);
+            let mut offset = parse_error.offset();
+            for pattern in patterns {
+                let len = pattern.chars().count();
+                if offset <= len {
+                    eprintln!("  {}", pattern);
+                    eprintln!("  {}^", " ".repeat(offset));
+                    break;
+                }
+                offset -= len + 1;
+            }
+        }
     }
-        
 }
 
-impl Iterator for PathIterator {
-    type Item = String;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        while let Some(entry) = self.queue.pop_front() {
-            let path = Path::new(&entry);
-            if path.is_file() {
-                return Some(entry);
-            }
-
-            if path.is_dir() {
-                if !self.is_recursive {
-                    panic!("Expected file or -r for directory");
-                }
-
-                if let Ok(entries) = fs::read_dir(&path) {
-                    for entry in entries.flatten() {
-                        self.queue.push_back(entry.path().to_str().unwrap().to_string());
+// searches the paths one by one, writing straight to stdout
+fn search_sequential(paths: VecDeque<String>, args: &Args, searcher: &mut Searcher, implicit_dot: bool) -> Status {
+    let mut status = Status::default();
+    let mut stdout = TrackedWriter::new(io::stdout().lock());
+    for path in PathIterator::new(paths, args) {
+        match path {
+            Ok(path) => {
+                let name = display_name(&path, implicit_dot);
+                match searcher.search_path(&path, &name, &mut stdout) {
+                    Err(err) if stdout.failed() => {
+                        status.record_write_error(&err);
+                        break;
                     }
+                    result => status.record(&name, result, args.no_messages),
                 }
-                continue;
             }
-
-            panic!("Invalid path");
+            Err((path, err)) => status.record(&path, Err(err), args.no_messages),
         }
-
-        None
     }
+    status
 }
 
-
-
-
 fn main() {
 
     env_logger::init();
 
-    let valid_args: Vec<String> = vec!["-E".to_string(), "-r".to_string()];
-    let mut args = env::args().collect::<VecDeque<String>>();
-    args.pop_front();
-
-    let first_arg = args.pop_front().expect("Expected at least one argument");
-    let is_recursive: bool;
-    if !valid_args.contains(&first_arg) {
-        eprintln!("Expected first argument to be '-E' or '-r'");
-        process::exit(1);
-    }
-    else {
-        is_recursive = first_arg == "-r";
-    }
-
-    if is_recursive {
-        let second_arg = args.pop_front().expect("Expected -E argument");
-        if second_arg != "-E" {
-            eprintln!("Expected second argument to be '-E'");
-            process::exit(1);
+    let args = match args::parse_args(env::args().skip(1)) {
+        Ok(Command::Search(args)) => *args,
+        Ok(Command::Help) => {
+            print!("{}", args::help());
+            process::exit(0);
+        }
+        Ok(Command::Version) => {
+            print!("{}", args::version());
+            process::exit(0);
         }
+        Err(err) => {
+            eprintln!("grep: {}", err);
+            eprintln!("{}", args::usage());
+            eprintln!("Try '{} --help' for more information.", env!("CARGO_PKG_NAME"));
+            process::exit(2);
+        }
+    };
+    let regex = RegexBuilder::new()
+        .case_insensitive(args.ignore_case)
+        .multi_line(true)
+        .recursion_limit(search::RECURSION_LIMIT)
+        .build_many(&args.patterns)
+        .unwrap_or_else(|err| {
+            report_pattern_error(&args.patterns, &err);
+            process::exit(2);
+        });
+
+    let mut paths: VecDeque<String> = args.paths.iter().cloned().collect();
+    // like GNU grep, -r without operands searches the working directory and
+    // names files relative to it
+    let implicit_dot = paths.is_empty() && args.recursive;
+    if paths.is_empty() {
+        paths.push_back(if args.recursive { ".".to_string() } else { "-".to_string() });
     }
-
-    let pattern = args.pop_front().expect("Expected pattern argument");
-    let mut match_flag: bool = false;
-
-    if !args.is_empty() {
-        let if_print_filename: bool = is_recursive || args.len() > 1;
-        PathIterator::new(args.clone(), is_recursive).for_each(|path| {
-            let file = File::open(&path).unwrap();
-            let reader = BufReader::new(file);
-            for line in reader.lines() {
-                let line_str = line.unwrap();
-                let match_flag_single_line = parse::Parser::match_pattern(&line_str, &pattern);
-                if match_flag_single_line {
-                    match_flag = true;
-                    if if_print_filename {
-                        println!("{}:{}", &path, line_str);
-                    }
-                    else {
-                        println!("{}", line_str);
-                    }
-                }
-            }
-        })
-        
+    let if_print_filename: bool = args.recursive || paths.len() > 1;
+
+    let colors = args.color.enabled().then(Colors::from_env);
+    let mut searcher = Searcher::new(&regex, &args, colors.as_ref(), if_print_filename);
+    let jobs = match args.threads {
+        0 => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+        jobs => jobs,
+    };
+    // a single input is searched on one thread so its output streams, a
+    // thread of its own for the stack deep matches need
+    let status = if jobs > 1 && if_print_filename {
+        parallel::search(paths, &args, &searcher, implicit_dot, jobs)
     }
     else {
-        let mut input_line = String::new();
-        io::stdin().read_line(&mut input_line).unwrap();
-        match_flag = parse::Parser::match_pattern(&input_line, &pattern);
-    }
-    
-    if match_flag {
+        thread::scope(|scope| {
+            thread::Builder::new()
+                .stack_size(search::STACK_SIZE)
+                .spawn_scoped(scope, || search_sequential(paths, &args, &mut searcher, implicit_dot))
+                .expect("failed to spawn the search thread")
+                .join()
+                .unwrap()
+        })
+    };
+
+    // like GNU grep, an error wins over having found matches
+    if status.failed {
+        process::exit(2)
+    } else if status.matched {
         process::exit(0)
     } else {
         process::exit(1)
@@ -0,0 +1,524 @@
+//! Thompson NFA compiled from the AST, run by a Pike VM: all threads move
+//! through the text together, so a search takes time proportional to the
+//! size of the program times the length of the text whatever the pattern.
+//! Patterns with back references cannot be compiled and stay with the
+//! backtracker.
+
+use crate::ast::{fold_case, CharClass, Node};
+use crate::backtrack::{at_line_end, at_line_start, decode_unit, Slots};
+
+#[derive(Debug, Clone)]
+pub(crate) enum Inst {
+    /// a byte of a literal
+    Byte(u8),
+    /// a char of a case-insensitive literal, already case folded
+    Folded(char),
+    Dot,
+    Class(CharClass),
+    StartAnchor,
+    EndAnchor,
+    /// records the position in a slot: group starts and ends, or where the
+    /// current iteration of a repetition started
+    Save(usize),
+    /// fails when the iteration started in the slot matched nothing
+    Progress(usize),
+    /// tries both, preferring the first
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// The program for one pattern. Slots `2 * i` and `2 * i + 1` hold where
+/// group `i` starts and ends, group 0 being the whole match; iteration
+/// starts come after them.
+#[derive(Debug, Clone)]
+pub(crate) struct Nfa {
+    pub(crate) insts: Vec<Inst>,
+    group_count: usize,
+    slot_count: usize,
+}
+
+impl Nfa {
+    /// Compiles `node`, or returns `None` when it has back references.
+    pub(crate) fn new(node: &Node, group_count: usize) -> Option<Self> {
+        let mut compiler = Compiler { insts: Vec::new(), slot_count: 2 * (group_count + 1) };
+        compiler.push(Inst::Save(0));
+        compiler.compile(node)?;
+        compiler.push(Inst::Save(1));
+        compiler.push(Inst::Match);
+        debug_assert_eq!(compiler.insts.len(), compiled_size(node));
+        Some(Self { insts: compiler.insts, group_count, slot_count: compiler.slot_count })
+    }
+}
+
+/// How many instructions `Nfa::new` would compile `node` to, counting a
+/// back reference as one; saturates instead of overflowing.
+pub(crate) fn compiled_size(node: &Node) -> usize {
+    // the `Save` around the pattern and the final `Match`
+    3usize.saturating_add(size(node))
+}
+
+fn size(node: &Node) -> usize {
+    match node {
+        Node::Empty => 0,
+        Node::Literal(literal) => literal.len(),
+        Node::FoldedLiteral(literal) => literal.chars().count(),
+        Node::Group { node, .. } => size(node).saturating_add(2),
+        Node::Concat(nodes) => nodes.iter().fold(0, |total, node| total.saturating_add(size(node))),
+        // a `Split` and a `Jump` around every branch but the last
+        Node::Alternation(branches) => branches
+            .iter()
+            .fold(2 * branches.len().saturating_sub(1), |total, branch| total.saturating_add(size(branch))),
+        Node::Repeat { node, min, max } => {
+            let once = size(node);
+            // an optional iteration also takes a `Split`, a `Save` and a `Progress`
+            let optional = match max {
+                None => once.saturating_add(4),
+                Some(max) => once.saturating_add(3).saturating_mul((max - min) as usize),
+            };
+            once.saturating_mul(*min as usize).saturating_add(optional)
+        }
+        _ => 1,
+    }
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+    slot_count: usize,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    fn next(&self) -> usize {
+        self.insts.len()
+    }
+
+    fn compile(&mut self, node: &Node) -> Option<()> {
+        match node {
+            Node::Empty => {}
+            Node::Literal(literal) => literal.bytes().for_each(|byte| {
+                self.push(Inst::Byte(byte));
+            }),
+            Node::FoldedLiteral(literal) => literal.chars().for_each(|c| {
+                self.push(Inst::Folded(c));
+            }),
+            Node::Dot => {
+                self.push(Inst::Dot);
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()));
+            }
+            Node::StartAnchor => {
+                self.push(Inst::StartAnchor);
+            }
+            Node::EndAnchor => {
+                self.push(Inst::EndAnchor);
+            }
+            Node::Group { index, node } => {
+                self.push(Inst::Save(2 * index));
+                self.compile(node)?;
+                self.push(Inst::Save(2 * index + 1));
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternation(branches) => {
+                let mut jumps = Vec::new();
+                let (last, rest) = branches.split_last()?;
+                for branch in rest {
+                    let split = self.push(Inst::Split(0, 0));
+                    self.compile(branch)?;
+                    jumps.push(self.push(Inst::Jump(0)));
+                    self.insts[split] = Inst::Split(split + 1, self.next());
+                }
+                self.compile(last)?;
+                let end = self.next();
+                for jump in jumps {
+                    self.insts[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat { node, min, max } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.push(Inst::Split(0, 0));
+                        self.compile_iteration(node)?;
+                        self.push(Inst::Jump(split));
+                        self.insts[split] = Inst::Split(split + 1, self.next());
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0)));
+                            self.compile_iteration(node)?;
+                        }
+                        let end = self.next();
+                        for split in splits {
+                            self.insts[split] = Inst::Split(split + 1, end);
+                        }
+                    }
+                }
+            }
+            Node::Backreference(_) | Node::FoldedBackreference(_) => return None,
+        }
+        Some(())
+    }
+
+    // an iteration past the minimum, which like in the backtracker may not
+    // match the empty string
+    fn compile_iteration(&mut self, node: &Node) -> Option<()> {
+        let slot = self.slot_count;
+        self.slot_count += 1;
+        self.push(Inst::Save(slot));
+        self.compile(node)?;
+        self.push(Inst::Progress(slot));
+        Some(())
+    }
+}
+
+// a thread waiting at `pc` with its slots in `row`; `sleep` counts the
+// bytes still to go of a unit it is in the middle of consuming
+#[derive(Clone, Copy)]
+struct Thread {
+    pc: usize,
+    row: usize,
+    sleep: usize,
+}
+
+// the slots of the threads in rows of `width`; threads share a row until a
+// `Save` changes their slots, and a row nobody uses is taken again
+struct Rows {
+    width: usize,
+    slots: Vec<Option<usize>>,
+    users: Vec<usize>,
+    free: Vec<usize>,
+}
+
+impl Rows {
+    fn new(width: usize) -> Self {
+        Self { width, slots: Vec::new(), users: Vec::new(), free: Vec::new() }
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+        self.users.clear();
+        self.free.clear();
+    }
+
+    fn get(&self, row: usize) -> &[Option<usize>] {
+        &self.slots[row * self.width..][..self.width]
+    }
+
+    // a row of its own holding `slots`
+    fn alloc(&mut self, slots: &[Option<usize>]) -> usize {
+        let row = match self.free.pop() {
+            Some(row) => {
+                self.slots[row * self.width..][..self.width].copy_from_slice(slots);
+                row
+            }
+            None => {
+                self.slots.extend_from_slice(slots);
+                self.users.push(0);
+                self.users.len() - 1
+            }
+        };
+        self.users[row] = 1;
+        row
+    }
+
+    fn share(&mut self, row: usize) -> usize {
+        self.users[row] += 1;
+        row
+    }
+
+    fn release(&mut self, row: usize) {
+        self.users[row] -= 1;
+        if self.users[row] == 0 {
+            self.free.push(row);
+        }
+    }
+}
+
+/// A set of pcs below a fixed bound that clears in constant time, so that
+/// a step costs as much as the threads alive and not the whole program.
+pub(crate) struct SparseSet {
+    dense: Vec<usize>,
+    // where each member sits in `dense`; stale for the others
+    sparse: Vec<usize>,
+}
+
+impl SparseSet {
+    pub(crate) fn new(len: usize) -> Self {
+        Self { dense: Vec::new(), sparse: vec![0; len] }
+    }
+
+    pub(crate) fn contains(&self, pc: usize) -> bool {
+        self.dense.get(self.sparse[pc]) == Some(&pc)
+    }
+
+    /// Adds `pc`, returning whether it was not in the set yet.
+    pub(crate) fn insert(&mut self, pc: usize) -> bool {
+        if self.contains(pc) {
+            return false;
+        }
+        self.sparse[pc] = self.dense.len();
+        self.dense.push(pc);
+        true
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+// the threads at one position in priority order, each pc at most once
+struct Threads {
+    list: Vec<Thread>,
+    seen: SparseSet,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Self { list: Vec::new(), seen: SparseSet::new(len) }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.clear();
+    }
+}
+
+/// What a Pike VM search needs besides the text, kept from one search to
+/// the next so that searching allocates nothing once it has warmed up.
+pub(crate) struct Cache {
+    current: Threads,
+    next: Threads,
+    rows: Rows,
+    stack: Vec<Frame>,
+    // the slots `add` follows once a `Save` changed them
+    scratch: Vec<Option<usize>>,
+    // the slots of the best match so far
+    best: Vec<Option<usize>>,
+}
+
+impl Cache {
+    pub(crate) fn new(nfa: &Nfa) -> Self {
+        Self {
+            current: Threads::new(nfa.insts.len()),
+            next: Threads::new(nfa.insts.len()),
+            rows: Rows::new(nfa.slot_count),
+            stack: Vec::new(),
+            scratch: vec![None; nfa.slot_count],
+            best: vec![None; nfa.slot_count],
+        }
+    }
+}
+
+enum Frame {
+    Explore(usize),
+    Restore(usize, Option<usize>),
+}
+
+/// Runs an `Nfa` over `text`, with the same modes as the backtracker.
+pub(crate) struct PikeVm<'t> {
+    text: &'t [u8],
+    utf8: bool,
+    multi_line: bool,
+}
+
+impl<'t> PikeVm<'t> {
+    pub(crate) fn new(text: &'t [u8], utf8: bool) -> Self {
+        Self { text, utf8, multi_line: false }
+    }
+
+    pub(crate) fn multi_line(mut self, yes: bool) -> Self {
+        self.multi_line = yes;
+        self
+    }
+
+    /// Returns the slots of the leftmost match starting at `start` or
+    /// later, preferring among matches at the same start what the
+    /// backtracker would find first.
+    pub(crate) fn search(&self, nfa: &Nfa, start: usize, cache: &mut Cache) -> Option<Slots> {
+        let Cache { current, next, rows, stack, scratch, best } = cache;
+        current.clear();
+        next.clear();
+        rows.clear();
+        let mut matched = false;
+        // where the next unit starts, the only places a match may start
+        let mut next_start = Some(start);
+        let mut at = start;
+        loop {
+            if !matched && next_start == Some(at) {
+                scratch.fill(None);
+                let row = rows.alloc(scratch);
+                self.add(nfa, current, rows, stack, scratch, 0, at, row);
+                rows.release(row);
+                next_start = decode_unit(&self.text[at..], self.utf8).map(|(_, len)| at + len);
+            }
+            if current.list.is_empty() && (matched || next_start.is_none()) {
+                break;
+            }
+
+            let unit = decode_unit(&self.text[at..], self.utf8);
+            for &thread in &current.list {
+                if thread.sleep > 0 {
+                    if thread.sleep == 1 {
+                        self.add(nfa, next, rows, stack, scratch, thread.pc, at + 1, thread.row);
+                    }
+                    else {
+                        next.list.push(Thread { row: rows.share(thread.row), sleep: thread.sleep - 1, ..thread });
+                    }
+                    continue;
+                }
+                let len = match &nfa.insts[thread.pc] {
+                    Inst::Match => {
+                        // threads after this one have lower priority
+                        best.copy_from_slice(rows.get(thread.row));
+                        matched = true;
+                        break;
+                    }
+                    inst => self.consume(inst, at, unit),
+                };
+                let Some(len) = len else {
+                    continue;
+                };
+                if len == 1 {
+                    self.add(nfa, next, rows, stack, scratch, thread.pc + 1, at + 1, thread.row);
+                }
+                else {
+                    next.list.push(Thread { pc: thread.pc + 1, row: rows.share(thread.row), sleep: len - 1 });
+                }
+            }
+            // the threads that went on hold rows of their own
+            for thread in current.list.drain(..) {
+                rows.release(thread.row);
+            }
+            if at == self.text.len() {
+                break;
+            }
+            std::mem::swap(current, next);
+            next.clear();
+            at += 1;
+        }
+
+        if !matched {
+            return None;
+        }
+        let slots = best;
+        Some(
+            (0..=nfa.group_count)
+                .map(|group| match (slots[2 * group], slots[2 * group + 1]) {
+                    (Some(start), Some(end)) => Some((start, end)),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    // how many bytes a consuming instruction takes at `at`, if it matches
+    fn consume(&self, inst: &Inst, at: usize, unit: Option<(Option<char>, usize)>) -> Option<usize> {
+        let (c, len) = unit?;
+        if self.multi_line && c == Some('\n') {
+            return None;
+        }
+        match inst {
+            Inst::Byte(byte) => (self.text[at] == *byte).then_some(1),
+            // in byte mode only ASCII letters fold, other chars have to be
+            // there byte for byte
+            Inst::Folded(expected) if !self.utf8 && !expected.is_ascii() => {
+                let mut encoded = [0; 4];
+                let encoded = expected.encode_utf8(&mut encoded).as_bytes();
+                self.text[at..].starts_with(encoded).then_some(encoded.len())
+            }
+            Inst::Folded(expected) => c.is_some_and(|c| fold_case(c) == *expected).then_some(len),
+            Inst::Dot => c.map_or(!self.utf8, |c| c != '\n').then_some(len),
+            Inst::Class(class) => match c {
+                Some(c) => class.matches(c).then_some(len),
+                None => (class.negated && !self.utf8).then_some(len),
+            },
+            _ => None,
+        }
+    }
+
+    // adds the thread at `pc` with the slots in `row` to `threads`,
+    // following jumps, splits and assertions to the instructions that
+    // consume text, in priority order
+    #[allow(clippy::too_many_arguments)]
+    fn add(
+        &self,
+        nfa: &Nfa,
+        threads: &mut Threads,
+        rows: &mut Rows,
+        stack: &mut Vec<Frame>,
+        scratch: &mut [Option<usize>],
+        pc: usize,
+        at: usize,
+        row: usize,
+    ) {
+        // the saves made on the way to the current pc; without any the
+        // threads found share `row`, otherwise their slots are in `scratch`
+        let mut changed = 0;
+        stack.push(Frame::Explore(pc));
+        while let Some(frame) = stack.pop() {
+            let pc = match frame {
+                Frame::Explore(pc) => pc,
+                Frame::Restore(slot, saved) => {
+                    scratch[slot] = saved;
+                    changed -= 1;
+                    continue;
+                }
+            };
+            if threads.seen.contains(pc) {
+                continue;
+            }
+            // a failed check must not keep a later thread from passing
+            if let Inst::Progress(slot) = nfa.insts[pc] {
+                let slots = if changed == 0 { rows.get(row) } else { &*scratch };
+                if slots[slot] == Some(at) {
+                    continue;
+                }
+            }
+            threads.seen.insert(pc);
+            match nfa.insts[pc] {
+                Inst::Jump(target) => stack.push(Frame::Explore(target)),
+                Inst::Split(first, second) => {
+                    stack.push(Frame::Explore(second));
+                    stack.push(Frame::Explore(first));
+                }
+                Inst::Save(slot) => {
+                    if changed == 0 {
+                        scratch.copy_from_slice(rows.get(row));
+                    }
+                    changed += 1;
+                    stack.push(Frame::Restore(slot, scratch[slot]));
+                    scratch[slot] = Some(at);
+                    stack.push(Frame::Explore(pc + 1));
+                }
+                Inst::Progress(_) => stack.push(Frame::Explore(pc + 1)),
+                Inst::StartAnchor => {
+                    if at_line_start(self.text, at, self.multi_line) {
+                        stack.push(Frame::Explore(pc + 1));
+                    }
+                }
+                Inst::EndAnchor => {
+                    if at_line_end(self.text, at, self.multi_line) {
+                        stack.push(Frame::Explore(pc + 1));
+                    }
+                }
+                _ => {
+                    let row = if changed == 0 { rows.share(row) } else { rows.alloc(scratch) };
+                    threads.list.push(Thread { pc, row, sleep: 0 });
+                }
+            }
+        }
+    }
+}
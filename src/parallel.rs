@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use crate::args::Args;
+use crate::search::{display_name, Searcher, Status, STACK_SIZE};
+use crate::walk::PathIterator;
+
+// what searching one path produced, waiting for its turn to be printed
+struct Outcome {
+    name: String,
+    output: Vec<u8>,
+    printed: bool,
+    result: io::Result<bool>,
+}
+
+/// Walks `paths` on one thread while `jobs` workers search the files it
+/// yields, each file into a buffer of its own. Buffers are printed whole
+/// and in walk order, so output never interleaves and keeps the --sort
+/// order. A failed write to stdout stops the walk and the workers.
+pub fn search(paths: VecDeque<String>, args: &Args, searcher: &Searcher, implicit_dot: bool, jobs: usize) -> Status {
+    // bounded so the walk does not run too far ahead of the workers
+    let (path_tx, path_rx) = mpsc::sync_channel(jobs * 4);
+    let path_rx = Mutex::new(path_rx);
+    let (outcome_tx, outcome_rx) = mpsc::channel();
+    // set once the output is gone and nothing more is worth searching
+    let stop = AtomicBool::new(false);
+    let stop = &stop;
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for item in PathIterator::new(paths, args).enumerate() {
+                if stop.load(Ordering::Relaxed) || path_tx.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+
+        for _ in 0..jobs {
+            let path_rx = &path_rx;
+            let outcome_tx = outcome_tx.clone();
+            let worker = thread::Builder::new().stack_size(STACK_SIZE);
+            worker.spawn_scoped(scope, move || loop {
+                // the lock is released as soon as a path is taken
+                let received = path_rx.lock().unwrap().recv();
+                let Ok((index, path)) = received else {
+                    break;
+                };
+                // what the walk queued before it stopped is only drained, so
+                // that it never waits on a full channel
+                if stop.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let outcome = match path {
+                    Ok(path) => {
+                        let name = display_name(&path, implicit_dot);
+                        // a fresh clone, as no earlier input has printed yet
+                        let mut searcher = searcher.clone();
+                        let mut output = Vec::new();
+                        let result = searcher.search_path(&path, &name, &mut output);
+                        Outcome { name, output, printed: searcher.printed_any(), result }
+                    }
+                    Err((path, err)) => Outcome { name: path, output: Vec::new(), printed: false, result: Err(err) },
+                };
+                // fails only once the printing has stopped, which sets `stop`
+                let _ = outcome_tx.send((index, outcome));
+            })
+            .expect("failed to spawn a search thread");
+        }
+        drop(outcome_tx);
+
+        let mut status = Status::default();
+        let mut printed_any = false;
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        let mut stdout = io::stdout().lock();
+        'print: for (index, outcome) in outcome_rx {
+            pending.insert(index, outcome);
+            while let Some(outcome) = pending.remove(&next) {
+                next += 1;
+                let separate = outcome.printed && printed_any && searcher.has_context();
+                let written = if separate { searcher.print_separator(&mut stdout) } else { Ok(()) };
+                if let Err(err) = written.and_then(|_| stdout.write_all(&outcome.output)) {
+                    status.record_write_error(&err);
+                    stop.store(true, Ordering::Relaxed);
+                    break 'print;
+                }
+                printed_any |= outcome.printed;
+                status.record(&outcome.name, outcome.result, args.no_messages);
+            }
+        }
+        status
+    })
+}
@@ -1,14 +1,20 @@
-use std::collections::HashMap;
 use log;
 
+use crate::ast::{fold_case, CharClass, ClassItem, Node};
+use crate::error::ParseError;
+use crate::regex::Regex;
+
 #[derive(Debug, PartialEq)]
 pub enum QuantifierType {
     Plus,
     Question,
     Star,
-    Repitition((i32, i32))
+    Repitition((u32, u32))
 }
 
+/// The largest count a `{n,m}` repetition may give, POSIX's `RE_DUP_MAX`.
+pub const MAX_REPETITION: u32 = 32767;
+
 #[derive(Debug, PartialEq)]
 enum Token {
     Literal(String),
@@ -21,16 +27,31 @@ enum Token {
 #[derive(Clone)]
 pub struct Parser {
     pub chars: Vec<char>,
-    group_references: HashMap<String, String>,
-    active_groups: Vec<String>,
+    // offset in the full pattern just past our last char, so that group
+    // bodies parsed by their own Parser still report absolute offsets
+    end: usize,
+    // toggled by -i and by inline `(?i)` / `(?-i)` flags
+    pub(crate) case_insensitive: bool,
 }
 
 impl Parser {
 
     pub fn new(pattern: &str) -> Self {
-        Self {chars: pattern.chars().collect(), group_references: HashMap::new(), active_groups: Vec::new()}
+        Self::with_offset(pattern, 0)
     }
 
+    pub(crate) fn with_offset(pattern: &str, offset: usize) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let end = offset + chars.len();
+        Self {chars, end, case_insensitive: false}
+    }
+
+    /// Character offset of the next char in the full pattern.
+    pub fn position(&self) -> usize {
+        self.end - self.chars.len()
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<char>{
         if self.chars.is_empty() {
             return None;
@@ -53,7 +74,7 @@ impl Parser {
                 break;
             }
             else if quantifiers.contains(c) {
-                if literal.len() > 1 {
+                if literal.chars().count() > 1 {
                     let put_back = literal.pop().unwrap();
                     self.chars.insert(0, put_back);
                 }
@@ -86,9 +107,10 @@ impl Parser {
         else {None}
     }
 
-    pub fn parse_slash(&mut self) -> Option<String> {
+    pub fn parse_slash(&mut self) -> Result<Option<String>, ParseError> {
         if let Some(c) = self.peek() {
             if c == '\\' {
+                let offset = self.position();
                 let mut result = String::new();
                 result.push(c);
                 self.next();
@@ -97,37 +119,38 @@ impl Parser {
                         result.push(c);
                         self.next();
                     }
-                    return Some(result);
-                    
+                    Ok(Some(result))
                 }
-                else {panic!("Invalid escape sequence")}
+                else {Err(ParseError::TrailingBackslash { offset })}
             }
-            else {None}
+            else {Ok(None)}
         }
-        else {None}
+        else {Ok(None)}
     }
 
-    pub fn parse_char_class(&mut self) -> Option<String> {
+    pub fn parse_char_class(&mut self) -> Result<Option<String>, ParseError> {
         if let Some(c) = self.peek() {
             if c == '[' {
+                let offset = self.position();
                 let mut result = String::new();
                 while let Some(c) = self.next() {
                     result.push(c);
                     if c == ']' {
-                        return Some(result);
+                        return Ok(Some(result));
                     }
                 }
-                panic!("Unclosed character class");
+                Err(ParseError::UnclosedCharClass { offset })
             }
-            else {None}
+            else {Ok(None)}
         }
-        else {None}
+        else {Ok(None)}
     }
 
-    pub fn parse_quantifier(&mut self) -> Option<QuantifierType> {
+    pub fn parse_quantifier(&mut self) -> Result<Option<QuantifierType>, ParseError> {
         log::debug!("[QUANTIFIER] -> chars: {:?}", self.chars);
         let quantifiers = "+?*{";
         if let Some(c) = self.peek() {
+            let offset = self.position();
             let mut quantifier_string: Option<char> = None;
             if quantifiers.contains(c) {
                 quantifier_string = Some(c);
@@ -143,12 +166,12 @@ impl Parser {
                 }
             }
             else {
-                return None;
+                return Ok(None);
             }
             match quantifier_string {
-                Some('+') => return Some(QuantifierType::Plus),
-                Some('?') => return Some(QuantifierType::Question),
-                Some('*') => return Some(QuantifierType::Star),
+                Some('+') => return Ok(Some(QuantifierType::Plus)),
+                Some('?') => return Ok(Some(QuantifierType::Question)),
+                Some('*') => return Ok(Some(QuantifierType::Star)),
                 Some('{') => {
                     let mut result = String::new();
                     while let Some(c) = self.next() {
@@ -159,47 +182,71 @@ impl Parser {
                         if c == '}' {
                             result.pop(); // remove the }
                             log::debug!("[QUANTIFIER] -> [REP]: \"{}\"", result);
+                            let invalid = || ParseError::InvalidRepetition { offset, content: result.clone() };
                             let numbers = result
                             .split(',')
                             .filter(|x| !x.is_empty())
-                            .map(|x| x.parse::<i32>().unwrap())
-                            .collect::<Vec<i32>>();
+                            .map(|x| match x.parse::<u32>() {
+                                // digits only: no sign, and no count past RE_DUP_MAX
+                                Ok(n) if n <= MAX_REPETITION && x.bytes().all(|b| b.is_ascii_digit()) => Ok(n),
+                                _ => Err(invalid()),
+                            })
+                            .collect::<Result<Vec<u32>, ParseError>>()?;
+                            if result.matches(',').count() > 1 {
+                                return Err(invalid());
+                            }
                             if numbers.len() == 1 {
                                 if result.ends_with(',') {
-                                    return Some(QuantifierType::Repitition((numbers[0], i32::MAX)));
+                                    return Ok(Some(QuantifierType::Repitition((numbers[0], u32::MAX))));
                                 }
-                                return Some(QuantifierType::Repitition((numbers[0], numbers[0])));
+                                if result.starts_with(',') {
+                                    return Ok(Some(QuantifierType::Repitition((0, numbers[0]))));
+                                }
+                                return Ok(Some(QuantifierType::Repitition((numbers[0], numbers[0]))));
                             }
-                            else if numbers.len() == 2 {
-                                return Some(QuantifierType::Repitition((numbers[0], numbers[1])));
+                            else if numbers.len() == 2 && numbers[0] <= numbers[1] {
+                                return Ok(Some(QuantifierType::Repitition((numbers[0], numbers[1]))));
                             }
                             else {
-                                panic!("Invalid quantifier repetition");
+                                return Err(invalid());
                             }
                         }
                     }
-                    panic!("Unclosed quantifier repetition");
+                    return Err(ParseError::UnclosedRepetition { offset });
                 }
-                None => return None,
-                _ => panic!("Invalid quantifier")
+                _ => return Ok(None),
             }
         }
         
-        None
+        Ok(None)
     }
 
-    pub fn parse_parentheses(&mut self) -> Option<(String, bool)> {
+    pub fn parse_parentheses(&mut self) -> Result<Option<(String, bool)>, ParseError> {
         let mut is_group = true;
         if let Some(c) = self.peek() {
             let mut depth = 0;
             if c == '(' {
+                let offset = self.position();
                 let mut result = String::new();
+                let mut in_class = false;
                 while let Some(c) = self.next() {
                     result.push(c);
-                    if c == ')' {
+                    if c == '\\' {
+                        // an escaped character never opens or closes anything
+                        if let Some(escaped) = self.next() {
+                            result.push(escaped);
+                        }
+                    }
+                    else if in_class {
+                        in_class = c != ']';
+                    }
+                    else if c == '[' {
+                        in_class = true;
+                    }
+                    else if c == ')' {
                         depth -= 1;
                         if depth == 0 {
-                            return Some((result, is_group));
+                            return Ok(Some((result, is_group)));
                         }
                     }
                     else if c == '|' {
@@ -211,10 +258,10 @@ impl Parser {
                         depth += 1;
                     }
                 }
-                panic!("Unclosed parentheses");
+                return Err(ParseError::UnclosedParenthesis { offset });
             }
         }
-        None
+        Ok(None)
     }
 
     pub fn split_alternatives(group: &str) -> Vec<String> {
@@ -261,368 +308,242 @@ impl Parser {
         None
     }
 
-    fn slice_based_on_char(s: &str, start: usize) -> String {
-        let mut char_indices = s.char_indices();
-    
-        let byte_start = match char_indices.nth(start) {
-            Some((byte_idx, _)) => byte_idx,
-            None => s.len(), // start beyond end means return empty string
-        };
-    
-        s[byte_start..].to_string()
-    }
 
     pub fn match_pattern(input: &str, pattern: &str) -> bool {
-        let mut parser = Parser::new(pattern);
-        let (flag, _, _) = Self::match_pattern_internal(input, &mut parser, false, None, true);
+        let flag = match Regex::new(pattern) {
+            Ok(regex) => regex.is_match(input),
+            Err(_) => false,
+        };
         log::debug!("-------> match pattern done flag: {}", flag);
         flag
     }
 
-    fn match_token_atom(input: &str, token: &Token, start_anchor: bool, group_references: &HashMap<String, String>, is_special: bool) -> (bool, i32, String) {
-        let mut matched: String = String::new();
-        match token {
-            Token::CharClass(token) => {
-                let mut len_input_checked: usize = 0;
-                let mut flag = false;
-                for c in input.chars() {
-                    len_input_checked += 1;
-                    if token[1..token.len() - 1].contains(c) && !token.starts_with("[^") {
-                        matched.push(c);
-                        flag = true;
-                        break
-                    }
-                    else if token.starts_with("[^") && !token.contains(c) {
-                        matched.push(c);
-                        flag = true;
-                        break
-                    }
-                }
-                if !flag {
-                    return (false, 0, matched);
-                }
-                else if start_anchor && len_input_checked > 1 {
-                    return (false, 0, matched);
-                }
-                else {
-                    return (flag, len_input_checked as i32, matched);
+    /// Parses the whole pattern into its AST, returning it along with the
+    /// number of capturing groups.
+    pub fn parse_regex(&mut self) -> Result<(Node, usize), ParseError> {
+        let mut group_count = 0;
+        let node = self.parse_alternation(&mut group_count)?;
+        Ok((node, group_count))
+    }
+
+    fn parse_alternation(&mut self, group_count: &mut usize) -> Result<Node, ParseError> {
+        let mut branches = Vec::new();
+        let mut items = Vec::new();
+
+        loop {
+            match self.peek() {
+                None => break,
+                Some('|') => {
+                    self.next();
+                    branches.push(Self::concat(std::mem::take(&mut items)));
+                    continue;
                 }
+                Some(')') => return Err(ParseError::UnmatchedParenthesis { offset: self.position() }),
+                _ => {}
             }
-            Token::Slash(token) => {
-                if input.is_empty() { return (false, 0, matched); }
-                let token_char = token.chars().nth(1).unwrap();
-                for (index, c) in input.chars().enumerate() {
-                    if c.is_digit(10) && token_char == 'd' {
-                        if start_anchor && index > 0 {
-                            return (false, 0, matched);
-                        }
-                        matched.push(c);
-                        return (true, index as i32 + 1, matched);
-                    }
-                    else if (c.is_alphanumeric() || c == '_') && token_char == 'w' {
-                        if start_anchor && index > 0 {
-                            return (false, 0, matched);
-                        }
-                        matched.push(c);
-                        return (true, index as i32 + 1, matched);
-                    }
-                    if token_char.is_digit(10) {
-                        return Self::match_token_atom(input, &Token::Literal(group_references.get(&token.to_string()).unwrap().to_string()), start_anchor, group_references, true);
-                    }
-                }
-                return (false, 0, matched);
+
+            if self.parse_start_anchor().is_some() {
+                items.push(Node::StartAnchor);
+                continue;
             }
-            Token::Dot => {
-                if input.is_empty() { return (false, 0, matched); }
-                matched.push(input.chars().nth(0).unwrap());
-                return (true, 1, matched);
+            if self.parse_end_anchor().is_some() {
+                items.push(Node::EndAnchor);
+                continue;
             }
-            Token::Literal(token) => {
-                if input.len() < token.len() { return (false, 0, matched); }
 
-                if !is_special {
-                    if input.starts_with(token) {
-                        return (true, token.len() as i32, token.to_string());
-                    }
-                    else {
-                        return (false, 0, matched);
-                    }
+            let offset = self.position();
+            let atom = match Self::get_next_token(self)? {
+                Some(token) => self.compile_token(token, offset, group_count)?,
+                // a stray `]` is not special outside of a class
+                None if self.peek() == Some(']') => {
+                    self.next();
+                    Node::Literal("]".to_string())
                 }
-                
-                for index in 0..input.len() - token.len() + 1 {
-                    if Self::slice_based_on_char(&input, index).starts_with(token) {
-                        if start_anchor && index > 0 {
-                            return (false, 0, matched);
+                // a quantifier with nothing in front of it repeats the empty string
+                None => Node::Empty,
+            };
+
+            let node = match self.parse_quantifier()? {
+                Some(quantifier) => {
+                    let (min, max) = match quantifier {
+                        QuantifierType::Plus => (1, None),
+                        QuantifierType::Question => (0, Some(1)),
+                        QuantifierType::Star => (0, None),
+                        QuantifierType::Repitition((n, m)) => {
+                            (n, if m == u32::MAX { None } else { Some(m) })
                         }
-                        matched = token.to_string();
-                        return (true, index as i32 + token.len() as i32, matched);
-                    }
+                    };
+                    Node::Repeat { node: Box::new(atom), min, max }
                 }
-                return (false, 0, matched);
-            }
-            Token::Parentheses((token, _)) => {
-                let alternates: Vec<String> = Self::split_alternatives(&token);
-                for alternate in alternates {
-                    let mut new_parser = Parser::new(&alternate);
-                    new_parser.group_references = group_references.clone();
-                    let (flag, len, matched) = Self::match_pattern_internal(&input, &mut new_parser, start_anchor, Some(true), false);
-                    if flag {
-                        return (true, len, matched);
-                    }
-                }
-                return (false, 0, matched);
-            }
+                None => atom,
+            };
+            items.push(node);
         }
-        
-    }
 
-    fn get_next_token(parser: &mut Parser) -> Option<Token> {
-        let mut token: Option<Token> = None;
-        if let Some(pattern) = parser.parse_char_class() {
-            token = Some(Token::CharClass(pattern));
-        }
-        else if let Some((pattern, _is_group)) = parser.parse_parentheses() {
-            token = Some(Token::Parentheses((pattern, _is_group)));
+        if branches.is_empty() {
+            return Ok(Self::concat(items));
         }
-        else if let Some(pattern) = parser.parse_slash() {
-            if pattern.len() > 1 {
-                token = Some(Token::Slash(pattern));
-            }
-            else {
-                token = Some(Token::Literal("\\".to_string()));
-            }
-        }
-        else if let Some(_) = parser.parse_dot() {
-            token = Some(Token::Dot);
-        }
-        else if let Some(literal) = parser.parse_literal() {
-            token = Some(Token::Literal(literal));
-        }
-        token
+        branches.push(Self::concat(items));
+        Ok(Node::Alternation(branches))
     }
 
-    fn match_pattern_internal(input: &str, 
-        parser: &mut Parser, 
-        mut start_anchor: bool, 
-        is_special_entry: Option<bool>, 
-        start: bool,
-    ) -> (bool, i32, String) {
-        log::debug!("[START] -> input: \"{}\", pattern: {:?} group_references: {:?}", input, &parser.chars.clone().into_iter().collect::<String>(), parser.group_references);
-
-        let matched: String = String::new();
-        let mut input_check = input.to_string();
-        let mut is_special = true;
-        
-        while parser.peek() == Some(')') {
-            parser.next();
-            parser.active_groups.pop();
-        }
-
-
-        if let Some(_) = parser.parse_start_anchor() {
-            start_anchor = true;
+    fn concat(mut items: Vec<Node>) -> Node {
+        match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap(),
+            _ => Node::Concat(items),
         }
+    }
 
-        if let Some(_) = parser.parse_end_anchor() {
-            if input.is_empty() {
-                return (true, 0, matched);
-            }
-            else {
-                return (false, 0, matched);
-            }
+    fn compile_literal(&self, literal: String) -> Node {
+        if self.case_insensitive {
+            Node::FoldedLiteral(literal.chars().map(fold_case).collect())
         }
-
-        if parser.peek().is_none() {
-            return (true, 0, matched);
+        else {
+            Node::Literal(literal)
         }
+    }
 
-
-        let mut token = Self::get_next_token(parser);
-        if matches!(token, Some(Token::Literal(_))) {
-            if is_special_entry.is_some() {
-                is_special = is_special_entry.unwrap();
+    fn compile_token(&mut self, token: Token, offset: usize, group_count: &mut usize) -> Result<Node, ParseError> {
+        let node = match token {
+            Token::Literal(literal) => self.compile_literal(literal),
+            Token::Dot => Node::Dot,
+            Token::CharClass(class) => {
+                Node::Class(Self::compile_class(&class).with_case_insensitive(self.case_insensitive))
+            }
+            Token::Slash(slash) => {
+                let c = slash.chars().nth(1).unwrap();
+                match c {
+                    'd' => Node::Class(CharClass::new(vec![ClassItem::Digit], false)),
+                    'D' => Node::Class(CharClass::new(vec![ClassItem::Digit], true)),
+                    'w' => Node::Class(CharClass::new(vec![ClassItem::Word], false)),
+                    'W' => Node::Class(CharClass::new(vec![ClassItem::Word], true)),
+                    's' => Node::Class(CharClass::new(vec![ClassItem::Space], false)),
+                    'S' => Node::Class(CharClass::new(vec![ClassItem::Space], true)),
+                    '1'..='9' => {
+                        let index = c.to_digit(10).unwrap() as usize;
+                        if index > *group_count {
+                            return Err(ParseError::InvalidBackreference { offset, index });
+                        }
+                        if self.case_insensitive {
+                            Node::FoldedBackreference(index)
+                        }
+                        else {
+                            Node::Backreference(index)
+                        }
+                    }
+                    _ => self.compile_literal(c.to_string()),
+                }
             }
-            else {
-                is_special = false;
+            Token::Parentheses((group, _)) if group[1..].starts_with('?') => {
+                self.compile_flag_group(&group, offset, group_count)?
             }
-            if parser.peek() == Some('$') {
-                is_special = true;
+            Token::Parentheses((group, _)) => {
+                *group_count += 1;
+                let index = *group_count;
+                let mut inner = Parser::with_offset(&group[1..group.len() - 1], offset + 1);
+                inner.case_insensitive = self.case_insensitive;
+                let node = inner.parse_alternation(group_count)?;
+                Node::Group { index, node: Box::new(node) }
             }
-            if start {
-                is_special = true;
+        };
+        Ok(node)
+    }
+
+    // `(?i)` and `(?-i)` switch case folding for the rest of the enclosing
+    // group, `(?i:...)` only inside the (non-capturing) group it opens
+    fn compile_flag_group(&mut self, group: &str, offset: usize, group_count: &mut usize) -> Result<Node, ParseError> {
+        let body = &group[2..group.len() - 1];
+        let (flags, rest) = match body.find(':') {
+            Some(colon) => (&body[..colon], Some(&body[colon + 1..])),
+            None => (body, None),
+        };
+
+        let mut case_insensitive = self.case_insensitive;
+        let mut enable = true;
+        for (index, flag) in flags.chars().enumerate() {
+            match flag {
+                '-' if enable => enable = false,
+                'i' => case_insensitive = enable,
+                _ => return Err(ParseError::UnknownFlag { offset: offset + 2 + index, flag }),
             }
         }
 
-        while let Some(Token::Parentheses((pattern, group_flag))) = &token {
-            
-            parser.group_references.insert(format!("\\{}", parser.group_references.len() + 1), "".to_string());
-            parser.active_groups.push(format!("\\{}", parser.group_references.len()));
-
-            if *group_flag {
-                // add new parser chars to the start of the parser and ) will be the sign for the end of the group
-                let pattern_to_add = pattern.chars().skip(1).collect::<String>();
-                parser.chars.splice(0..0, pattern_to_add.chars());
-                token = Self::get_next_token(parser);
+        match rest {
+            Some(rest) => {
+                let rest_offset = offset + 2 + flags.chars().count() + 1;
+                let mut inner = Parser::with_offset(rest, rest_offset);
+                inner.case_insensitive = case_insensitive;
+                inner.parse_alternation(group_count)
             }
-            else {
-                // just add ) to the start of the parser so we can know when group is ended
-                parser.chars.insert(0, ')');
-                break;
+            None => {
+                self.case_insensitive = case_insensitive;
+                Ok(Node::Empty)
             }
         }
+    }
 
-        let quantifier = parser.parse_quantifier();
-
-        log::debug!("[TOKEN] {:?}, active_groups: {:?}, pattern: {} quantifier: {:?}", token, parser.active_groups, parser.chars.clone().into_iter().collect::<String>(), quantifier);
+    fn compile_class(class: &str) -> CharClass {
+        let mut body = class[1..class.len() - 1].chars().peekable();
+        let negated = body.peek() == Some(&'^');
+        if negated {
+            body.next();
+        }
 
-        if let Some(token) = token {
-            let (is_match, mut atom_len, mut matched) =  Self::match_token_atom(&input_check, &token, start_anchor, &parser.group_references, is_special);
-            log::debug!("[FIRST MATCH]: is_match: {}, atom_len: {}, matched: {}, token: {:?}, quantifier: {:?}", is_match, atom_len, matched, token, quantifier);
-            if is_match {
-                for group in &parser.active_groups {
-                    let string_to_add = parser.group_references.get(group).unwrap().to_string() + &matched.to_string();
-                    parser.group_references.insert(group.clone(), string_to_add);
-                }
-                log::debug!("[GR1] -> group_references: {:?}", parser.group_references);
-                match quantifier {
-                    Some(QuantifierType::Plus) | Some(QuantifierType::Star) => {
-                        loop {
-                            log::debug!("[1+] -> atom_len: {}, input_check: \"{}\"", atom_len, input_check);
-                            input_check = Self::slice_based_on_char(&input_check, atom_len as usize);
-                            let mut new_parser = parser.clone();
-                            log::debug!("[2+] -> input_check: \"{}\", new_parser: {:?}", input_check, new_parser.chars.clone().into_iter().collect::<String>());
-                            let (flag, len, matched_inside) = Self::match_pattern_internal(&input_check, &mut new_parser, true, None, false);
-                            log::debug!("[3+] -> flag: {}, len: {}, matched_inside: {}", flag, len, matched_inside);
-                            if flag {
-                                matched.push_str(&matched_inside);
-                                for group in &parser.active_groups {
-                                    let string_to_add = parser.group_references.get(group).unwrap().to_string() + &matched.to_string();
-                                    parser.group_references.insert(group.clone(), string_to_add);
-                                }
-                                log::debug!("[GR2] -> group_references: {:?}", parser.group_references);
-                                return (true, len, matched);
-                            }
-                            else {
-                                log::debug!("[4+] -> input_check: \"{}\", token: {:?}", input_check, token);
-                                let (is_matched_inside, len_inside, matched_inside) = Self::match_token_atom(&input_check, &token, true, &parser.group_references, is_special);
-                                log::debug!("[5+] -> is_matched_inside: {}, len_inside: {}, matched_inside: {}", is_matched_inside, len_inside, matched_inside);
-                                if is_matched_inside {
-                                    matched.push_str(&matched_inside);
-                                    for group in &parser.active_groups {
-                                        let string_to_add = parser.group_references.get(group).unwrap().to_string() + &matched_inside.to_string();
-                                        parser.group_references.insert(group.clone(), string_to_add);
-                                    }
-                                    log::debug!("[GR3] -> group_references: {:?}", parser.group_references);
-                                    atom_len = len_inside;
-                                }
-                                else {
-                                    return (false, 0, matched);
-                                }
-                            }
-                        }
-                    }
-                    Some(QuantifierType::Question) | None => {
-                        input_check = Self::slice_based_on_char(&input_check, atom_len as usize);
-                        let (flag, len, matched_inside) = Self::match_pattern_internal(&input_check, parser, true, None, false);
-                        matched.push_str(&matched_inside);
-                        return (flag, atom_len as i32 + len, matched);
-                    }
-                    Some(QuantifierType::Repitition((n, m))) => {
-                        // we already matched 1, so we need to match at least n-1 times
-                        input_check = Self::slice_based_on_char(&input_check, atom_len as usize);
-                        log::debug!("[REP START] -> input_check: \"{}\", m: {}, n: {}", input_check, m, n);
-                        for _ in 0..n-1 {
-                            let (flag, atom_len, matched_inside) = Self::match_token_atom(&input_check, &token, true, &parser.group_references, is_special);
-                            input_check = Self::slice_based_on_char(&input_check, atom_len as usize);
-                            if flag {
-                                matched.push_str(&matched_inside);
-                            }
-                            else {
-                                return (false, 0, matched);
-                            }
-                        }
-                        log::debug!("[REP - AFTER N] -> input_check: \"{}\"", input_check);
-                        for group in &parser.active_groups {
-                            let string_to_add = parser.group_references.get(group).unwrap().to_string() + &matched.to_string();
-                            parser.group_references.insert(group.clone(), string_to_add);
-                        }
-                        if m == n {
-                            let (flag, len, matched_inside) = Self::match_pattern_internal(&input_check, parser, true, Some(is_special), false);
-                            matched.push_str(&matched_inside);
-                            if flag {
-                                return (true, len, matched);
-                            }
-                        }
-                        else {
-                            let mut counter = n;
-                            
-                            loop {
-                                
-                                log::debug!("[REPMN] -> input_check: \"{}\", matched: \"{}\"", input_check, matched);
-                                let mut new_parser = parser.clone();
-                                let (flag, len, matched_inside) = Self::match_pattern_internal(&input_check, &mut new_parser, true, None, false);
-                                log::debug!("[REPMN] -> input_check: \"{}\", flag: {}, len: {}, matched_inside: {}", input_check, flag, len, matched_inside);
-                                
-                                if flag {
-                                    matched.push_str(&matched_inside);
-                                    for group in &parser.active_groups {
-                                        let string_to_add = parser.group_references.get(group).unwrap().to_string() + &matched.to_string();
-                                        parser.group_references.insert(group.clone(), string_to_add);
-                                    }
-                                    
-                                    return (true, len, matched);
-                                }
-                                else {
-                                    
-                                    let (is_matched_inside, len_inside, matched_inside) = Self::match_token_atom(&input_check, &token, true, &parser.group_references, is_special);
-                                    log::debug!("[REPMN] -> input_check: \"{}\", is_matched_inside: {}, len_inside: {}, matched_inside: {}", input_check, is_matched_inside, len_inside, matched_inside);
-                                    input_check = Self::slice_based_on_char(&input_check, len_inside as usize);
-                                    log::debug!("[REPMN] -> input_check: \"{}\"", input_check);
-                                    if is_matched_inside {
-                                        matched.push_str(&matched_inside);
-                                        for group in &parser.active_groups {
-                                            let string_to_add = parser.group_references.get(group).unwrap().to_string() + &matched_inside.to_string();
-                                            parser.group_references.insert(group.clone(), string_to_add);
-                                        }
-                                        
-                                    }
-                                    else {
-                                        return (false, 0, matched);
-                                    }
-                                }
-                                counter += 1;
-                                log::debug!("[REPMN] -> counter: {}, m: {}", counter, m);
-                                if counter > m {
-                                    return (false, 0, matched);
-                                }
-                            }
-                            
-                        }
-                        
-                    }
+        let mut items = Vec::new();
+        while let Some(c) = body.next() {
+            let item = if c == '\\' {
+                match body.next() {
+                    Some('d') => ClassItem::Digit,
+                    Some('w') => ClassItem::Word,
+                    Some('s') => ClassItem::Space,
+                    Some(escaped) => ClassItem::Char(escaped),
+                    None => ClassItem::Char('\\'),
                 }
-                
             }
             else {
-                if quantifier.is_some() && (quantifier == Some(QuantifierType::Question) || quantifier == Some(QuantifierType::Star)) {
-                    let (flag, len, matched_inside) = Self::match_pattern_internal(&input_check, parser, true, Some(is_special), false);
-                    matched.push_str(&matched_inside);
-                    if flag {
-                        return (true, len, matched);
+                ClassItem::Char(c)
+            };
+
+            // `a-z`, but a leading or trailing `-` is literal
+            if let ClassItem::Char(lo) = item {
+                if body.peek() == Some(&'-') {
+                    let mut ahead = body.clone();
+                    ahead.next();
+                    if let Some(hi) = ahead.next() {
+                        body = ahead;
+                        items.push(ClassItem::Range(lo, hi));
+                        continue;
                     }
                 }
-                return (false, 0, matched);
             }
-            
+            items.push(item);
         }
-        else {
-            if quantifier.is_some() {
-                if quantifier.unwrap() == QuantifierType::Question {return (true, 0, matched);}
-            return (false, 0, matched);
+        CharClass::new(items, negated)
+    }
+
+    fn get_next_token(parser: &mut Parser) -> Result<Option<Token>, ParseError> {
+        let mut token: Option<Token> = None;
+        if let Some(pattern) = parser.parse_char_class()? {
+            token = Some(Token::CharClass(pattern));
+        }
+        else if let Some((pattern, _is_group)) = parser.parse_parentheses()? {
+            token = Some(Token::Parentheses((pattern, _is_group)));
+        }
+        else if let Some(pattern) = parser.parse_slash()? {
+            if pattern.len() > 1 {
+                token = Some(Token::Slash(pattern));
+            }
+            else {
+                token = Some(Token::Literal("\\".to_string()));
             }
         }
-        
-        (false, 0, matched)
+        else if parser.parse_dot().is_some() {
+            token = Some(Token::Dot);
+        }
+        else if let Some(literal) = parser.parse_literal() {
+            token = Some(Token::Literal(literal));
+        }
+        Ok(token)
     }
 }
-
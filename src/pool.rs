@@ -0,0 +1,47 @@
+//! Scratch space that a regex keeps from one search to the next.
+
+use std::fmt;
+use std::sync::Mutex;
+
+/// Values reused between searches, one per search running at a time so
+/// that threads sharing a regex do not wait on each other.
+pub(crate) struct Pool<T> {
+    // the value of a search running alone, used in place under its lock
+    owner: Mutex<Option<T>>,
+    // those of the searches running next to it
+    others: Mutex<Vec<T>>,
+}
+
+impl<T> Pool<T> {
+    pub(crate) fn new() -> Self {
+        Self { owner: Mutex::new(None), others: Mutex::new(Vec::new()) }
+    }
+
+    /// Runs `f` on what `fresh` makes of a value left by an earlier search,
+    /// if there is one. Searching one line after another, as the CLI does,
+    /// takes one lock each; only searches running at the same time go to
+    /// the others.
+    pub(crate) fn with<R>(&self, fresh: impl FnOnce(Option<T>) -> T, f: impl FnOnce(&mut T) -> R) -> R {
+        if let Ok(mut owner) = self.owner.try_lock() {
+            let value = fresh(owner.take());
+            return f(owner.insert(value));
+        }
+        let mut value = fresh(self.others.lock().unwrap().pop());
+        let result = f(&mut value);
+        self.others.lock().unwrap().push(value);
+        result
+    }
+}
+
+// a fresh regex gets fresh scratch space
+impl<T> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Pool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool").finish_non_exhaustive()
+    }
+}
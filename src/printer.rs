@@ -0,0 +1,148 @@
+use std::io::{self, Write};
+
+use codecrafters_grep::bytes::Regex;
+
+use crate::args::Args;
+use crate::color::Colors;
+
+/// Prints the selected lines of one input, prefixing them with whatever
+/// the flags ask for, in GNU grep's `path:line:byte:` order. Lines are
+/// raw bytes and are written out as they are.
+pub struct Printer<'a> {
+    out: &'a mut dyn Write,
+    regex: &'a Regex,
+    path: Option<&'a str>,
+    only_matching: bool,
+    line_number: bool,
+    byte_offset: bool,
+    // highlights the matches of selected lines, or with -v those of context
+    // lines, as only those lines match
+    highlight: bool,
+    colors: Option<&'a Colors>,
+}
+
+impl<'a> Printer<'a> {
+    /// `colors` is `None` when output is not colored.
+    pub fn new(
+        regex: &'a Regex,
+        args: &Args,
+        colors: Option<&'a Colors>,
+        path: Option<&'a str>,
+        out: &'a mut dyn Write,
+    ) -> Self {
+        Self {
+            out,
+            regex,
+            path,
+            only_matching: args.only_matching,
+            line_number: args.line_number,
+            byte_offset: args.byte_offset,
+            highlight: !args.invert_match,
+            colors,
+        }
+    }
+
+    fn paint(&self, sgr: impl Fn(&Colors) -> &str, text: &str) -> String {
+        match self.colors {
+            Some(colors) => colors.paint(sgr(colors), text),
+            None => text.to_string(),
+        }
+    }
+
+    fn paint_bytes(&self, sgr: impl Fn(&Colors) -> &str, text: &[u8]) -> Vec<u8> {
+        match self.colors {
+            Some(colors) => colors.paint_bytes(sgr(colors), text),
+            None => text.to_vec(),
+        }
+    }
+
+    // writes the prefix and the line followed by a newline in one go
+    fn emit(&mut self, prefix: &str, line: &[u8]) -> io::Result<()> {
+        self.out.write_all(prefix.as_bytes())?;
+        self.out.write_all(line)?;
+        self.out.write_all(b"\n")
+    }
+
+    // wraps every non-empty match of the line in the color `sgr` picks
+    fn highlight(&self, line: &[u8], sgr: impl Fn(&Colors) -> &str) -> Vec<u8> {
+        let mut highlighted = Vec::new();
+        let mut last = 0;
+        for m in self.regex.find_iter(line).filter(|m| !m.is_empty()) {
+            highlighted.extend_from_slice(&line[last..m.start()]);
+            highlighted.extend(self.paint_bytes(&sgr, m.as_bytes()));
+            last = m.end();
+        }
+        highlighted.extend_from_slice(&line[last..]);
+        highlighted
+    }
+
+    // `offset` is the byte offset of the start of the line in its input;
+    // selected lines use `:` after each field and context lines `-`
+    fn prefix(&self, line_number: usize, offset: usize, separator: char) -> String {
+        let separator = self.paint(|colors| &colors.separator, &separator.to_string());
+        let mut prefix = String::new();
+        if let Some(path) = self.path {
+            prefix.push_str(&self.paint(|colors| &colors.filename, path));
+            prefix.push_str(&separator);
+        }
+        if self.line_number {
+            prefix.push_str(&self.paint(|colors| &colors.line_number, &line_number.to_string()));
+            prefix.push_str(&separator);
+        }
+        if self.byte_offset {
+            prefix.push_str(&self.paint(|colors| &colors.byte_offset, &offset.to_string()));
+            prefix.push_str(&separator);
+        }
+        prefix
+    }
+
+    /// Prints a selected line, or with -o each non-empty match on its own
+    /// line, where -b then reports the offset of the match itself.
+    pub fn print_selected(&mut self, line: &[u8], line_number: usize, offset: usize) -> io::Result<()> {
+        if self.only_matching {
+            for m in self.regex.find_iter(line).filter(|m| !m.is_empty()) {
+                let text = self.paint_bytes(|colors| &colors.selected_match, m.as_bytes());
+                self.emit(&self.prefix(line_number, offset + m.start(), ':'), &text)?;
+            }
+            Ok(())
+        }
+        else if self.colors.is_some() && self.highlight {
+            self.emit(&self.prefix(line_number, offset, ':'), &self.highlight(line, |colors| &colors.selected_match))
+        }
+        else {
+            self.emit(&self.prefix(line_number, offset, ':'), line)
+        }
+    }
+
+    /// With -v the context lines are the matching ones, and their matches
+    /// are colored with `mc`.
+    pub fn print_context(&mut self, line: &[u8], line_number: usize, offset: usize) -> io::Result<()> {
+        if self.colors.is_some() && !self.highlight {
+            self.emit(&self.prefix(line_number, offset, '-'), &self.highlight(line, |colors| &colors.context_match))
+        }
+        else {
+            self.emit(&self.prefix(line_number, offset, '-'), line)
+        }
+    }
+
+    /// Printed between groups of context that are not adjacent.
+    pub fn print_separator(&mut self) -> io::Result<()> {
+        self.emit("", self.paint(|colors| &colors.separator, "--").as_bytes())
+    }
+
+    pub fn print_count(&mut self, count: usize) -> io::Result<()> {
+        let prefix = match self.path {
+            Some(path) => {
+                let path = self.paint(|colors| &colors.filename, path);
+                format!("{}{}", path, self.paint(|colors| &colors.separator, ":"))
+            }
+            None => String::new(),
+        };
+        self.emit(&prefix, count.to_string().as_bytes())
+    }
+
+    /// Printed instead of the lines of a binary input once one is selected.
+    pub fn print_binary_match(&mut self, name: &str) -> io::Result<()> {
+        self.emit("", format!("Binary file {} matches", name).as_bytes())
+    }
+}
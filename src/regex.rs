@@ -0,0 +1,414 @@
+use std::fmt;
+use std::ops::{Index, Range};
+use std::str::FromStr;
+
+use log;
+
+use crate::ast::Node;
+use crate::backtrack::{Backtracker, Limits, Slots};
+use crate::dfa::Dfa;
+use crate::error::{Error, MatchError, ParseError};
+use crate::literal::Prefilter;
+use crate::nfa::{self, compiled_size, Nfa, PikeVm};
+use crate::parse::Parser;
+use crate::pool::Pool;
+
+/// A pattern parsed once into its AST, ready to be matched against any
+/// number of haystacks.
+///
+/// Patterns with back references are matched by backtracking, within the
+/// limits set on the builder. The `try_` methods report a search that went
+/// over them as a `MatchError`; the others treat it as finding no match.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    ast: Node,
+    group_count: usize,
+    prefilter: Option<Prefilter>,
+    // `None` when the pattern has back references, which only the
+    // backtracker can match
+    nfa: Option<Nfa>,
+    dfa: Dfa,
+    pike_vm: Pool<nfa::Cache>,
+    limits: Limits,
+}
+
+/// Configures how patterns are compiled. `Regex::new` and `Regex::new_many`
+/// use the defaults.
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    case_insensitive: bool,
+    size_limit: usize,
+    limits: Limits,
+}
+
+// enough for any sensible use of counted repetition, like `.{1,10000}`
+const DEFAULT_SIZE_LIMIT: usize = 250_000;
+
+impl Default for RegexBuilder {
+    fn default() -> Self {
+        Self { case_insensitive: false, size_limit: DEFAULT_SIZE_LIMIT, limits: Limits::default() }
+    }
+}
+
+impl RegexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches letters regardless of case, as if the pattern started with
+    /// `(?i)`.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// How many instructions a pattern may compile to, counted repetitions
+    /// being unrolled, before `build` fails with `ParseError::TooLarge`;
+    /// 250,000 by default.
+    pub fn size_limit(&mut self, insts: usize) -> &mut Self {
+        self.size_limit = insts;
+        self
+    }
+
+    /// How many steps the backtracker may take over one search, trying
+    /// every start position, before giving up with
+    /// `MatchError::BudgetExceeded`; in multi-line mode every line gets the
+    /// whole budget. Ten million by default.
+    pub fn step_budget(&mut self, steps: usize) -> &mut Self {
+        self.limits.steps = steps;
+        self
+    }
+
+    /// How deeply the backtracker may nest while matching from one
+    /// position before giving up with `MatchError::RecursionLimitExceeded`;
+    /// it grows with the length of a match, except for repeated chars and
+    /// classes like `\w+`. The default of 1000 suits the 2 MiB stack of a
+    /// spawned thread; a higher limit needs a bigger stack, about 2 KiB
+    /// more per level.
+    pub fn recursion_limit(&mut self, depth: usize) -> &mut Self {
+        self.limits.depth = depth;
+        self
+    }
+
+    pub fn build(&self, pattern: &str) -> Result<Regex, Error> {
+        self.build_many([pattern])
+    }
+
+    /// Compiles several patterns into one regex that matches wherever any of
+    /// them does, like `grep -e a -e b`. Groups are numbered across all the
+    /// patterns in order, while back references stay local to their own
+    /// pattern. Error offsets count the patterns as if joined by newlines.
+    pub fn build_many<I, S>(&self, patterns: I) -> Result<Regex, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut sources = Vec::new();
+        let mut branches = Vec::new();
+        let mut group_count = 0;
+        let mut offset = 0;
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            let mut parser = Parser::with_offset(pattern, offset);
+            parser.case_insensitive = self.case_insensitive;
+            let (mut node, count) = parser.parse_regex()?;
+            node.shift_groups(group_count);
+            group_count += count;
+            offset += pattern.chars().count() + 1;
+            branches.push(node);
+            sources.push(pattern.to_string());
+        }
+
+        let ast = if branches.len() == 1 { branches.pop().unwrap() } else { Node::Alternation(branches) };
+        let pattern = sources.join("\n");
+        log::debug!("[AST] patterns: {:?}, groups: {}, ast: {:?}", sources, group_count, ast);
+        if compiled_size(&ast) > self.size_limit {
+            return Err(ParseError::TooLarge { offset: 0, limit: self.size_limit }.into());
+        }
+        let prefilter = Prefilter::new(&ast);
+        log::debug!("[PREFILTER] {:?}", prefilter);
+        let nfa = Nfa::new(&ast, group_count);
+        let dfa = Dfa::new(nfa.as_ref());
+        Ok(Regex { pattern, ast, group_count, prefilter, nfa, dfa, pike_vm: Pool::new(), limits: self.limits })
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, Error> {
+        RegexBuilder::new().build(pattern)
+    }
+
+    /// Shorthand for `RegexBuilder::new().build_many(patterns)`.
+    pub fn new_many<I, S>(patterns: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        RegexBuilder::new().build_many(patterns)
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.try_is_match(haystack).unwrap_or(false)
+    }
+
+    pub fn try_is_match(&self, haystack: &str) -> Result<bool, MatchError> {
+        Ok(self.match_end(haystack.as_bytes(), 0, true, false)?.is_some())
+    }
+
+    /// Returns the leftmost match in `haystack`, if any.
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Like `find`, but starts looking at byte offset `start`. Anchors still
+    /// see the whole haystack, so `^` can only match at offset 0.
+    pub fn find_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        self.try_find_at(haystack, start).unwrap_or(None)
+    }
+
+    pub fn try_find_at<'h>(&self, haystack: &'h str, start: usize) -> Result<Option<Match<'h>>, MatchError> {
+        let Some(slots) = self.search_at(haystack, start)? else {
+            return Ok(None);
+        };
+        let (start, end) = slots[0].unwrap();
+        Ok(Some(Match::new(haystack, start, end)))
+    }
+
+    /// Returns the leftmost match along with the span of every capturing
+    /// group.
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        self.captures_at(haystack, 0)
+    }
+
+    pub fn captures_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Captures<'h>> {
+        self.try_captures_at(haystack, start).unwrap_or(None)
+    }
+
+    pub fn try_captures_at<'h>(&self, haystack: &'h str, start: usize) -> Result<Option<Captures<'h>>, MatchError> {
+        Ok(self.search_at(haystack, start)?.map(|slots| Captures { haystack, slots }))
+    }
+
+    /// Iterates over successive non-overlapping matches. An empty match
+    /// right where the previous match ended is skipped, so `a*` over `"baa"`
+    /// yields the empty match at 0 and then `"aa"`. Iteration stops at a
+    /// search that goes over the backtracking limits.
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> Matches<'r, 'h> {
+        Matches { captures: self.captures_iter(haystack) }
+    }
+
+    pub fn captures_iter<'r, 'h>(&'r self, haystack: &'h str) -> CapturesMatches<'r, 'h> {
+        CapturesMatches { regex: self, haystack, at: 0, last_end: None }
+    }
+
+    fn search_at(&self, haystack: &str, start: usize) -> Result<Option<Slots>, MatchError> {
+        match (start..=haystack.len()).find(|&at| haystack.is_char_boundary(at)) {
+            Some(start) => self.search_bytes(haystack.as_bytes(), start, true, false),
+            None => Ok(None),
+        }
+    }
+
+    fn backtracker<'t>(&self, text: &'t [u8], utf8: bool, multi_line: bool) -> Backtracker<'t> {
+        Backtracker::new(text, utf8).multi_line(multi_line).limits(self.limits)
+    }
+
+    fn pike_vm(&self, nfa: &Nfa, text: &[u8], start: usize, utf8: bool, multi_line: bool) -> Option<Slots> {
+        let fresh = |cache: Option<nfa::Cache>| cache.unwrap_or_else(|| nfa::Cache::new(nfa));
+        self.pike_vm.with(fresh, |cache| PikeVm::new(text, utf8).multi_line(multi_line).search(nfa, start, cache))
+    }
+
+    // where some match from `start` on ends, without working out where it
+    // starts when the lazy DFA can tell
+    pub(crate) fn match_end(
+        &self,
+        text: &[u8],
+        start: usize,
+        utf8: bool,
+        multi_line: bool,
+    ) -> Result<Option<usize>, MatchError> {
+        let match_end = |from: usize, to: usize| match &self.nfa {
+            Some(nfa) if self.dfa.supports(utf8) => Ok(self.dfa.match_end(nfa, &text[..to], from, utf8, multi_line)),
+            Some(nfa) => {
+                let slots = self.pike_vm(nfa, &text[..to], from, utf8, multi_line);
+                Ok(slots.and_then(|slots| slots[0]).map(|(_, end)| end))
+            }
+            None => {
+                let slots = self.backtracker(&text[..to], utf8, multi_line).search(&self.ast, from, self.group_count)?;
+                Ok(slots.and_then(|slots| slots[0]).map(|(_, end)| end))
+            }
+        };
+        match &self.prefilter {
+            Some(prefilter) => prefilter.search(text, start, multi_line, match_end),
+            None => match_end(start, text.len()),
+        }
+    }
+
+    // searches `text` from `start` on, looking for a required literal first
+    pub(crate) fn search_bytes(
+        &self,
+        text: &[u8],
+        start: usize,
+        utf8: bool,
+        multi_line: bool,
+    ) -> Result<Option<Slots>, MatchError> {
+        let search = |from: usize, to: usize| match &self.nfa {
+            Some(nfa) => Ok(self.pike_vm(nfa, &text[..to], from, utf8, multi_line)),
+            None => self.backtracker(&text[..to], utf8, multi_line).search(&self.ast, from, self.group_count),
+        };
+        match &self.prefilter {
+            Some(prefilter) => prefilter.search(text, start, multi_line, search),
+            None => search(start, text.len()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn ast(&self) -> &Node {
+        &self.ast
+    }
+
+    /// Number of capturing groups, not counting the implicit whole match.
+    pub fn group_count(&self) -> usize {
+        self.group_count
+    }
+}
+
+impl FromStr for Regex {
+    type Err = Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Error> {
+        Self::new(pattern)
+    }
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// A single match: byte offsets into the haystack it was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h str,
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    fn new(haystack: &'h str, start: usize, end: usize) -> Self {
+        Self { haystack, start, end }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.start..self.end]
+    }
+}
+
+/// The spans of all capturing groups of a match. Group 0 is the whole
+/// match; a group that did not take part in the match is `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captures<'h> {
+    haystack: &'h str,
+    slots: Slots,
+}
+
+impl<'h> Captures<'h> {
+    pub fn get(&self, index: usize) -> Option<Match<'h>> {
+        let (start, end) = (*self.slots.get(index)?)?;
+        Some(Match::new(self.haystack, start, end))
+    }
+
+    /// The whole match, same as `get(0)`.
+    pub fn get_match(&self) -> Match<'h> {
+        self.get(0).unwrap()
+    }
+
+    /// Number of groups including group 0.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match<'h>>> + '_ {
+        (0..self.slots.len()).map(|index| self.get(index))
+    }
+}
+
+impl Index<usize> for Captures<'_> {
+    type Output = str;
+
+    /// Panics if the group does not exist or did not participate.
+    fn index(&self, index: usize) -> &str {
+        self.get(index)
+            .unwrap_or_else(|| panic!("no group at index {}", index))
+            .as_str()
+    }
+}
+
+pub struct CapturesMatches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    at: usize,
+    last_end: Option<usize>,
+}
+
+impl<'h> Iterator for CapturesMatches<'_, 'h> {
+    type Item = Captures<'h>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.at <= self.haystack.len() {
+            let captures = self.regex.captures_at(self.haystack, self.at)?;
+            let found = captures.get_match();
+            if found.is_empty() && Some(found.end()) == self.last_end {
+                // step over one char so we cannot report the same spot twice
+                self.at = match self.haystack[found.end()..].chars().next() {
+                    Some(c) => found.end() + c.len_utf8(),
+                    None => self.haystack.len() + 1,
+                };
+                continue;
+            }
+            self.at = found.end();
+            self.last_end = Some(found.end());
+            return Some(captures);
+        }
+        None
+    }
+}
+
+pub struct Matches<'r, 'h> {
+    captures: CapturesMatches<'r, 'h>,
+}
+
+impl<'h> Iterator for Matches<'_, 'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.captures.next().map(|captures| captures.get_match())
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use codecrafters_grep::bytes::Regex;
+use codecrafters_grep::MatchError;
+use memchr::{memchr, memchr_iter, memrchr};
+use memmap2::Mmap;
+
+use crate::args::{Args, BinaryFiles};
+use crate::color::Colors;
+use crate::printer::Printer;
+use crate::walk::DirectoryLoop;
+
+// files this large are memory-mapped rather than read line by line
+const MMAP_THRESHOLD: u64 = 256 * 1024;
+
+// how much of an in-memory input is looked at to tell whether it is binary
+const BINARY_CHECK_LEN: usize = 8 * 1024;
+
+/// How deeply the backtracker may nest, far beyond the library's default
+/// so that patterns with back references still match on lines of tens of
+/// KB; searches run on threads with `STACK_SIZE` for it.
+pub const RECURSION_LIMIT: usize = 100_000;
+
+/// The stack of the threads that search, enough for `RECURSION_LIMIT` even
+/// in a debug build. Only the part in use takes memory.
+pub const STACK_SIZE: usize = 256 << 20;
+
+/// Searches inputs one after another, keeping the state that spans files
+/// (whether a `--` separator is due before the next context group). Clones
+/// start out with that state of the original.
+#[derive(Clone)]
+pub struct Searcher<'a> {
+    regex: &'a Regex,
+    args: &'a Args,
+    colors: Option<&'a Colors>,
+    with_filename: bool,
+    before_lines: usize,
+    after_lines: usize,
+    printed_any: bool,
+}
+
+// what is known about the input being searched
+struct Input<'o> {
+    printer: Printer<'o>,
+    name: &'o str,
+    binary: bool,
+    selected_count: usize,
+    before: VecDeque<BufferedLine>,
+    after_left: usize,
+    last_printed: Option<usize>,
+}
+
+// a line kept around as possible leading context
+struct BufferedLine {
+    line_number: usize,
+    offset: usize,
+    text: Vec<u8>,
+}
+
+/// Whether the start of an input looks like binary data: a NUL byte, or
+/// bytes that are not UTF-8 (a sequence cut off by the end of the buffer
+/// is given the benefit of the doubt).
+fn looks_binary(buffer: &[u8]) -> bool {
+    if buffer.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(buffer) {
+        Ok(_) => false,
+        Err(err) => err.error_len().is_some(),
+    }
+}
+
+/// Prints `grep: path: reason` the way GNU grep words it, without the
+/// "(os error N)" suffix std adds to OS errors.
+pub fn report_path_error(path: &str, err: &io::Error) {
+    eprintln!("grep: {}: {}", path, describe(err));
+}
+
+fn describe(err: &io::Error) -> String {
+    let message = err.to_string();
+    match message.find(" (os error ") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    }
+}
+
+/// Output that remembers whether a write to it failed, so that an error
+/// coming out of a search can be told to be about the output rather than
+/// the input.
+pub struct TrackedWriter<W> {
+    inner: W,
+    failed: bool,
+}
+
+impl<W: Write> TrackedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, failed: false }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+impl<W: Write> Write for TrackedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf);
+        self.failed |= written.is_err();
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let flushed = self.inner.flush();
+        self.failed |= flushed.is_err();
+        flushed
+    }
+}
+
+/// How a path is called in output: stdin is "(standard input)", and with
+/// an implicit `.` operand paths are shown relative to it.
+pub fn display_name(path: &str, implicit_dot: bool) -> String {
+    match path {
+        "-" => "(standard input)".to_string(),
+        path if implicit_dot => path.strip_prefix("./").unwrap_or(path).to_string(),
+        path => path.to_string(),
+    }
+}
+
+/// What the exit status is made of.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Status {
+    pub matched: bool,
+    pub failed: bool,
+}
+
+impl Status {
+    /// Takes in the result of searching the input called `name`, reporting
+    /// an error unless -s asked not to. A directory loop is only a warning.
+    pub fn record(&mut self, name: &str, result: io::Result<bool>, no_messages: bool) {
+        match result {
+            Ok(selected) => self.matched |= selected,
+            Err(err) => {
+                if !no_messages {
+                    report_path_error(name, &err);
+                }
+                self.failed |= !DirectoryLoop::is(&err);
+            }
+        }
+    }
+
+    /// Takes in a failure to write the output, after which the run has to
+    /// stop. It is reported even with -s, unless the pipe was closed: then
+    /// whoever reads the output has simply stopped reading.
+    pub fn record_write_error(&mut self, err: &io::Error) {
+        if err.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("grep: write error: {}", describe(err));
+        }
+        self.failed = true;
+    }
+}
+
+impl<'a> Searcher<'a> {
+    /// `with_filename` prefixes output lines with the name of their input.
+    pub fn new(regex: &'a Regex, args: &'a Args, colors: Option<&'a Colors>, with_filename: bool) -> Self {
+        // context makes no sense when only counts or matched parts are printed
+        let with_context = !args.count && !args.only_matching;
+        Self {
+            regex,
+            args,
+            colors,
+            with_filename,
+            before_lines: if with_context { args.before_lines() } else { 0 },
+            after_lines: if with_context { args.after_lines() } else { 0 },
+            printed_any: false,
+        }
+    }
+
+    pub fn has_context(&self) -> bool {
+        self.before_lines > 0 || self.after_lines > 0
+    }
+
+    /// Whether any group of lines has been printed so far.
+    pub fn printed_any(&self) -> bool {
+        self.printed_any
+    }
+
+    /// Prints `--` on its own, for inputs whose output was put together
+    /// apart from each other.
+    pub fn print_separator(&self, out: &mut dyn Write) -> io::Result<()> {
+        Printer::new(self.regex, self.args, self.colors, None, out).print_separator()
+    }
+
+    // prints `--` when the group starting at `first_line` is not adjacent to
+    // the last printed line, including groups in an earlier input
+    fn separate(&mut self, printer: &mut Printer, first_line: usize, last_printed: Option<usize>) -> io::Result<()> {
+        if self.has_context() {
+            let gap = match last_printed {
+                Some(last) => first_line > last + 1,
+                None => self.printed_any,
+            };
+            if gap {
+                printer.print_separator()?;
+            }
+        }
+        self.printed_any = true;
+        Ok(())
+    }
+
+    /// Searches the file at `path`, or stdin for `-`. Regular files of at
+    /// least `MMAP_THRESHOLD` bytes are memory-mapped and searched as a
+    /// whole; stdin, pipes and small files are read line by line.
+    pub fn search_path(&mut self, path: &str, name: &str, out: &mut dyn Write) -> io::Result<bool> {
+        if path == "-" {
+            return self.search_lines(io::stdin().lock(), name, out);
+        }
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if metadata.is_file() && metadata.len() >= MMAP_THRESHOLD {
+            // SAFETY: the map is only read while searching; a file truncated
+            // by someone else meanwhile can fault, which grep tools accept
+            if let Ok(map) = unsafe { Mmap::map(&file) } {
+                return self.search_buffer(&map, name, out);
+            }
+        }
+        self.search_lines(BufReader::new(file), name, out)
+    }
+
+    // the per-input state given the start of the input, or `None` when a
+    // binary input is skipped
+    fn start_input<'o>(&self, out: &'o mut dyn Write, name: &'o str, head: &[u8]) -> io::Result<Option<Input<'o>>>
+    where
+        'a: 'o,
+    {
+        let binary = self.args.binary_files != BinaryFiles::Text && looks_binary(head);
+        let path = self.with_filename.then_some(name);
+        let mut printer = Printer::new(self.regex, self.args, self.colors, path, out);
+        if binary && self.args.binary_files == BinaryFiles::WithoutMatch {
+            if self.args.count {
+                printer.print_count(0)?;
+            }
+            return Ok(None);
+        }
+        Ok(Some(Input {
+            printer,
+            name,
+            binary,
+            selected_count: 0,
+            before: VecDeque::with_capacity(self.before_lines + 1),
+            after_left: 0,
+            last_printed: None,
+        }))
+    }
+
+    // prints `line` as selected or context as needed; returns false once
+    // the rest of the input needs no looking at
+    fn take_line(
+        &mut self,
+        input: &mut Input,
+        line: &[u8],
+        line_number: usize,
+        offset: usize,
+        selected: bool,
+    ) -> io::Result<bool> {
+        if selected {
+            input.selected_count += 1;
+            if input.binary && !self.args.count {
+                // GNU grep stops at the first selected line of a binary input
+                input.printer.print_binary_match(input.name)?;
+                return Ok(false);
+            }
+            if !self.args.count {
+                let first_line = input.before.front().map_or(line_number, |line| line.line_number);
+                self.separate(&mut input.printer, first_line, input.last_printed)?;
+                for line in input.before.drain(..) {
+                    input.printer.print_context(&line.text, line.line_number, line.offset)?;
+                }
+                input.printer.print_selected(line, line_number, offset)?;
+                input.last_printed = Some(line_number);
+                input.after_left = self.after_lines;
+            }
+        }
+        else if input.after_left > 0 {
+            input.printer.print_context(line, line_number, offset)?;
+            input.last_printed = Some(line_number);
+            input.after_left -= 1;
+        }
+        else if self.before_lines > 0 {
+            if input.before.len() == self.before_lines {
+                input.before.pop_front();
+            }
+            input.before.push_back(BufferedLine { line_number, offset, text: line.to_vec() });
+        }
+        Ok(true)
+    }
+
+    // whether the regex matches `line`; a line it gives up on is reported
+    // and taken as not matching
+    fn matches(&self, name: &str, line: &[u8], line_number: usize) -> bool {
+        self.regex.try_is_match(line).unwrap_or_else(|err| {
+            self.report_match_error(name, line_number, err);
+            false
+        })
+    }
+
+    fn report_match_error(&self, name: &str, line_number: usize, err: MatchError) {
+        if !self.args.no_messages {
+            eprintln!("grep: {}:{}: warning: {}; line taken as not matching", name, line_number, err);
+        }
+    }
+
+    fn finish_input(&self, mut input: Input) -> io::Result<bool> {
+        if self.args.count {
+            input.printer.print_count(input.selected_count)?;
+        }
+        Ok(input.selected_count > 0)
+    }
+
+    /// Searches one input line by line, printing the selected lines with
+    /// their context (or only their count with -c) to `out`; returns
+    /// whether any line was selected. `name` is how the input is called in
+    /// messages and prefixes. A read or write error ends the search of this
+    /// input.
+    pub fn search_lines<R: BufRead>(&mut self, mut reader: R, name: &str, out: &mut dyn Write) -> io::Result<bool> {
+        let head = reader.fill_buf()?;
+        let Some(mut input) = self.start_input(out, name, head)? else {
+            return Ok(false);
+        };
+
+        let mut line_number = 0;
+        let mut offset = 0;
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            let read = reader.read_until(b'\n', &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            line_number += 1;
+            let line = strip_line(&buffer);
+            let selected = self.matches(name, line, line_number) != self.args.invert_match;
+            if !self.take_line(&mut input, line, line_number, offset, selected)? {
+                return Ok(true);
+            }
+            offset += read;
+        }
+        self.finish_input(input)
+    }
+
+    /// Like `search_lines`, for an input that is in memory as a whole. When
+    /// only selected lines are printed the regex runs over the whole buffer
+    /// and lines are only cut out around its matches; otherwise every line
+    /// is looked at in place.
+    pub fn search_buffer(&mut self, buffer: &[u8], name: &str, out: &mut dyn Write) -> io::Result<bool> {
+        let head = &buffer[..buffer.len().min(BINARY_CHECK_LEN)];
+        let Some(mut input) = self.start_input(out, name, head)? else {
+            return Ok(false);
+        };
+
+        if self.args.invert_match || self.has_context() {
+            let mut line_number = 0;
+            let mut offset = 0;
+            while offset < buffer.len() {
+                let end = memchr(b'\n', &buffer[offset..]).map_or(buffer.len(), |index| offset + index + 1);
+                line_number += 1;
+                let line = strip_line(&buffer[offset..end]);
+                let selected = self.matches(name, line, line_number) != self.args.invert_match;
+                if !self.take_line(&mut input, line, line_number, offset, selected)? {
+                    return Ok(true);
+                }
+                offset = end;
+            }
+            return self.finish_input(input);
+        }
+
+        // newlines before `counted` are already in `line_number`
+        let mut line_number = 1;
+        let mut counted = 0;
+        let mut at = 0;
+        while at < buffer.len() {
+            // matches stay within a line, so where one ends (or, when it is
+            // empty, a newline right after it) tells the line
+            // an attempt over the limits gives up on the line it started in
+            let (found, exceeded) = match self.regex.try_shortest_match_at(buffer, at) {
+                Ok(Some(found)) => (found, None),
+                Ok(None) => break,
+                Err(err) => (err.offset(), Some(err)),
+            };
+            // there is no line after the last newline for `^$` to match
+            if found == buffer.len() && buffer.ends_with(b"\n") {
+                break;
+            }
+            let start = memrchr(b'\n', &buffer[..found]).map_or(0, |index| index + 1);
+            let end = memchr(b'\n', &buffer[found..]).map_or(buffer.len(), |index| found + index + 1);
+            line_number += memchr_iter(b'\n', &buffer[counted..start]).count();
+            counted = start;
+            // a match next to the `\r` of a CRLF line may not hold for the
+            // line as it is printed, so the line gets the final say
+            let line = strip_line(&buffer[start..end]);
+            let selected = match exceeded {
+                Some(err) => {
+                    self.report_match_error(name, line_number, err);
+                    false
+                }
+                None => self.matches(name, line, line_number),
+            };
+            if selected && !self.take_line(&mut input, line, line_number, start, true)? {
+                return Ok(true);
+            }
+            at = end;
+        }
+        self.finish_input(input)
+    }
+}
+
+// a line without its `\n` or `\r\n`
+fn strip_line(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
@@ -0,0 +1,367 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use codecrafters_grep::error::GlobError;
+use codecrafters_grep::glob::Glob;
+use thiserror::Error;
+
+use crate::args::{Args, SortBy};
+
+// ignore files read in every directory, later ones taking precedence
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+/// One line of an ignore file, in gitignore syntax; -g globs use the same
+/// syntax.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    // a pattern with a `/` before its end is matched against the path
+    // relative to the ignore file, any other against the file name alone
+    anchored: bool,
+}
+
+impl Rule {
+    /// Parses one line; blank lines and comments have no rule.
+    pub fn parse(line: &str) -> Result<Option<Rule>, GlobError> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        // trailing spaces are dropped unless escaped
+        let line = if line.ends_with("\\ ") { line } else { line.trim_end_matches(' ') };
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        let glob = Glob::new(line)?;
+        Ok(Some(Rule { glob, negated, dir_only, anchored }))
+    }
+
+    fn matches(&self, relative: &str, name: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        self.glob.is_match(if self.anchored { relative } else { name })
+    }
+}
+
+/// The ignore rules found in one directory, chained to those of the
+/// directories above it.
+struct Ignore {
+    dir: PathBuf,
+    // where `dir` sits below the directory of the ignore files, for the
+    // rules of the directories above an operand
+    base: PathBuf,
+    rules: Vec<Rule>,
+    parent: Option<Rc<Ignore>>,
+}
+
+impl Ignore {
+    /// Reads the ignore files of `dir`; without any rules the parent's
+    /// chain is used as is.
+    fn load(dir: &Path, parent: Option<Rc<Ignore>>) -> Option<Rc<Ignore>> {
+        let rules = Self::read_rules(dir);
+        if rules.is_empty() {
+            return parent;
+        }
+        Some(Rc::new(Ignore { dir: dir.to_path_buf(), base: PathBuf::new(), rules, parent }))
+    }
+
+    /// The chain of the directories from the root of the git repository
+    /// `operand` is in down to its parent, applied to the paths below it.
+    fn load_ancestors(operand: &Path) -> Option<Rc<Ignore>> {
+        let absolute = fs::canonicalize(operand).ok()?;
+        let root = absolute.ancestors().find(|dir| dir.join(".git").exists())?;
+        let above: Vec<&Path> = absolute.ancestors().skip(1).take_while(|dir| dir.starts_with(root)).collect();
+        let mut chain = None;
+        for dir in above.into_iter().rev() {
+            let rules = Self::read_rules(dir);
+            if !rules.is_empty() {
+                let base = absolute.strip_prefix(dir).unwrap_or(&absolute).to_path_buf();
+                chain = Some(Rc::new(Ignore { dir: operand.to_path_buf(), base, rules, parent: chain }));
+            }
+        }
+        chain
+    }
+
+    fn read_rules(dir: &Path) -> Vec<Rule> {
+        let mut files = Vec::new();
+        if dir.join(".git").is_dir() {
+            files.push(dir.join(".git").join("info").join("exclude"));
+        }
+        files.extend(IGNORE_FILES.iter().map(|name| dir.join(name)));
+
+        // a missing or unreadable ignore file just has no rules, and a line
+        // that is not a valid glob is skipped like git does
+        files
+            .iter()
+            .filter_map(|file| fs::read_to_string(file).ok())
+            .flat_map(|contents| {
+                contents.lines().filter_map(|line| Rule::parse(line).ok().flatten()).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The deepest directory with a rule for `path` decides, and within it
+    /// the last matching rule; a negated rule re-includes the path.
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let mut level = Some(self);
+        while let Some(ignore) = level {
+            if let Ok(relative) = path.strip_prefix(&ignore.dir) {
+                let relative = ignore.base.join(relative);
+                let relative = relative.to_string_lossy();
+                if let Some(rule) = ignore.rules.iter().rev().find(|rule| rule.matches(&relative, &name, is_dir)) {
+                    return !rule.negated;
+                }
+            }
+            level = ignore.parent.as_deref();
+        }
+        false
+    }
+}
+
+/// The --include, --exclude, --exclude-dir and -g selection of the entries
+/// found while recursing; all but -g also apply to operands.
+struct Filters {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    exclude_dir: Vec<Glob>,
+    globs: Vec<Rule>,
+}
+
+impl Filters {
+    // the last -g glob matching the path decides, a `!` one leaving it out;
+    // when only plain -g globs are given, files matching none are left out
+    fn glob_override(&self, relative: &str, name: &str, is_dir: bool) -> Option<bool> {
+        match self.globs.iter().rev().find(|rule| rule.matches(relative, name, is_dir)) {
+            Some(rule) => Some(!rule.negated),
+            None if !is_dir && self.globs.iter().any(|rule| !rule.negated) => Some(false),
+            None => None,
+        }
+    }
+
+    // --include/--exclude look at file names and --exclude-dir at the
+    // names of directories
+    fn selects(&self, name: &str, is_dir: bool) -> bool {
+        if is_dir {
+            return !self.exclude_dir.iter().any(|glob| glob.is_match(name));
+        }
+        !self.exclude.iter().any(|glob| glob.is_match(name))
+            && (self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(name)))
+    }
+}
+
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<FileId> {
+    None
+}
+
+/// A directory found again below itself by following symlinks. It is only
+/// worth a warning: the rest of the walk goes on and the exit status does
+/// not change.
+#[derive(Debug, Error)]
+#[error("warning: recursive directory loop")]
+pub struct DirectoryLoop;
+
+impl DirectoryLoop {
+    /// Whether `err` is a `DirectoryLoop`.
+    pub fn is(err: &io::Error) -> bool {
+        err.get_ref().is_some_and(|inner| inner.is::<DirectoryLoop>())
+    }
+}
+
+// a path waiting to be visited, with what it inherits from its directory
+struct Entry {
+    path: String,
+    // the operand it was found under, which -g globs are relative to
+    root: Rc<Path>,
+    // 0 for operands, 1 for what is directly inside them, and so on
+    depth: usize,
+    // device and inode of the directories above, to catch symlink loops
+    ancestors: Rc<Vec<FileId>>,
+    ignore: Option<Rc<Ignore>>,
+}
+
+/// Yields the files to search: operands as given, and with -r the files
+/// below directory operands, leaving out hidden, ignored and filtered out
+/// entries unless asked not to. Directories are walked depth first, their
+/// entries in --sort order. Symlinks given as operands are always followed.
+pub struct PathIterator {
+    // entries still to visit, the next one at the front
+    queue: VecDeque<Entry>,
+    is_recursive: bool,
+    // -R follows symlinks found while recursing, -r skips them
+    follow: bool,
+    hidden: bool,
+    no_ignore: bool,
+    filters: Filters,
+    sort: SortBy,
+    max_depth: Option<usize>,
+}
+
+impl PathIterator {
+    pub fn new(paths: VecDeque<String>, args: &Args) -> Self {
+        let queue = paths
+            .into_iter()
+            .map(|path| Entry {
+                root: Rc::from(Path::new(&path)),
+                path,
+                depth: 0,
+                ancestors: Rc::default(),
+                ignore: None,
+            })
+            .collect();
+        Self {
+            queue,
+            is_recursive: args.recursive,
+            follow: args.follow,
+            hidden: args.hidden,
+            no_ignore: args.no_ignore,
+            filters: Filters {
+                include: args.include.clone(),
+                exclude: args.exclude.clone(),
+                exclude_dir: args.exclude_dir.clone(),
+                globs: args.globs.clone(),
+            },
+            sort: args.sort,
+            max_depth: args.max_depth,
+        }
+    }
+
+    // like GNU grep, --include/--exclude leave out file operands too and
+    // with -r --exclude-dir directory operands; ignore files and -g globs
+    // never leave out an operand
+    fn skips_operand(&self, path: &str, is_dir: bool) -> bool {
+        if is_dir && !self.is_recursive {
+            return false;
+        }
+        Path::new(path).file_name().is_some_and(|name| !self.filters.selects(&name.to_string_lossy(), is_dir))
+    }
+
+    // queues the entries of `dir` that are not hidden, ignored or filtered
+    // out, so that excluded directories are never read; they go in front of
+    // the rest of the queue to be visited before the siblings of `dir`
+    fn read_dir(&mut self, dir: &Entry, ancestors: Rc<Vec<FileId>>) -> io::Result<()> {
+        let mut children = Vec::new();
+        let ignore = if self.no_ignore { None } else { Ignore::load(Path::new(&dir.path), dir.ignore.clone()) };
+        for dir_entry in fs::read_dir(&dir.path)? {
+            let dir_entry = dir_entry?;
+            let name = dir_entry.file_name().to_string_lossy().into_owned();
+            if !self.hidden && name.starts_with('.') {
+                continue;
+            }
+            let path = dir_entry.path();
+            let Ok(file_type) = dir_entry.file_type() else {
+                continue;
+            };
+            let is_dir = if file_type.is_symlink() {
+                if !self.follow {
+                    continue;
+                }
+                fs::metadata(&path).is_ok_and(|metadata| metadata.is_dir())
+            }
+            else {
+                file_type.is_dir()
+            };
+            let relative = path.strip_prefix(&dir.root).unwrap_or(&path).to_string_lossy();
+            let selected = match self.filters.glob_override(&relative, &name, is_dir) {
+                Some(selected) => selected,
+                None => !ignore.as_ref().is_some_and(|ignore| ignore.is_ignored(&path, is_dir)),
+            };
+            if !selected || !self.filters.selects(&name, is_dir) {
+                continue;
+            }
+            children.push(Entry {
+                path: path.to_string_lossy().into_owned(),
+                root: dir.root.clone(),
+                depth: dir.depth + 1,
+                ancestors: ancestors.clone(),
+                ignore: ignore.clone(),
+            });
+        }
+
+        // the stable sorts keep entries with the same key in path order
+        if self.sort != SortBy::None {
+            children.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        match self.sort {
+            SortBy::Path | SortBy::None => {}
+            SortBy::Modified => children.sort_by_cached_key(|entry| {
+                fs::symlink_metadata(&entry.path).and_then(|metadata| metadata.modified()).ok()
+            }),
+            SortBy::Size => {
+                children.sort_by_cached_key(|entry| fs::symlink_metadata(&entry.path).map(|metadata| metadata.len()).ok())
+            }
+        }
+        for child in children.into_iter().rev() {
+            self.queue.push_front(child);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for PathIterator {
+    /// A file to search, or a path that could not be searched with the reason.
+    type Item = Result<String, (String, io::Error)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(mut entry) = self.queue.pop_front() {
+            // `-` names standard input
+            if entry.path == "-" {
+                return Some(Ok(entry.path));
+            }
+            let metadata = match fs::metadata(&entry.path) {
+                Ok(metadata) => metadata,
+                Err(err) => return Some(Err((entry.path, err))),
+            };
+            if entry.depth == 0 && self.skips_operand(&entry.path, metadata.is_dir()) {
+                continue;
+            }
+            if !metadata.is_dir() {
+                return Some(Ok(entry.path));
+            }
+            if !self.is_recursive {
+                return Some(Err((entry.path, io::Error::other("Is a directory"))));
+            }
+            if self.max_depth.is_some_and(|max_depth| entry.depth >= max_depth) {
+                continue;
+            }
+            let mut ancestors = entry.ancestors.clone();
+            if let Some(id) = file_id(&metadata) {
+                if ancestors.contains(&id) {
+                    return Some(Err((entry.path, io::Error::other(DirectoryLoop))));
+                }
+                Rc::make_mut(&mut ancestors).push(id);
+            }
+            // the ignore files above a directory operand apply below it too
+            if entry.depth == 0 && !self.no_ignore {
+                entry.ignore = Ignore::load_ancestors(Path::new(&entry.path));
+            }
+            if let Err(err) = self.read_dir(&entry, ancestors) {
+                return Some(Err((entry.path, err)));
+            }
+        }
+
+        None
+    }
+}
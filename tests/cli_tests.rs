@@ -0,0 +1,1062 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates a fresh scratch directory holding the given `(relative path, contents)` files.
+fn scratch_dir(files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "grep-cli-{}-{}",
+        std::process::id(),
+        NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    for (path, contents) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run_grep(dir: &PathBuf, args: &[&str], stdin: &str) -> Output {
+    run_grep_with_env(dir, args, stdin, &[])
+}
+
+fn run_grep_with_env(dir: &PathBuf, args: &[&str], stdin: &str, vars: &[(&str, &str)]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-grep"))
+        .args(args)
+        .env_remove("GREP_COLORS")
+        .envs(vars.iter().copied())
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // grep may exit without reading stdin at all, so a broken pipe is fine
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+// runs grep with its output read by someone who goes away after the first
+// few bytes, like `grep ... | head -c 16`
+fn run_grep_into_closed_pipe(dir: &PathBuf, args: &[&str], stdin: Stdio) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-grep"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    stdout.read_exact(&mut [0; 16]).unwrap();
+    drop(stdout);
+    child.wait_with_output().unwrap()
+}
+
+fn stdout_of(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[cfg(test)]
+mod tests_only_matching {
+    use super::*;
+
+    #[test]
+    fn test_only_matching_prints_each_match() {
+        let dir = scratch_dir(&[("log.txt", "id=12 and id=345\nnothing here\n")]);
+        let output = run_grep(&dir, &["-o", "-E", "\\d+", "log.txt"], "");
+        assert_eq!(stdout_of(&output), "12\n345\n");
+        assert_eq!(output.status.code(), Some(0));
+    }
+
+    #[test]
+    fn test_only_matching_keeps_path_prefix() {
+        let dir = scratch_dir(&[("a.txt", "cat dog\n"), ("b.txt", "dogma\n")]);
+        let output = run_grep(&dir, &["-o", "-E", "dog", "a.txt", "b.txt"], "");
+        assert_eq!(stdout_of(&output), "a.txt:dog\nb.txt:dog\n");
+    }
+
+    #[test]
+    fn test_only_matching_recursive() {
+        let dir = scratch_dir(&[("tree/sub/c.txt", "192.168.0.1 up\n")]);
+        let output = run_grep(&dir, &["-r", "-o", "-E", "\\d+\\.\\d+", "tree"], "");
+        assert_eq!(stdout_of(&output), "tree/sub/c.txt:192.168\ntree/sub/c.txt:0.1\n");
+    }
+
+    #[test]
+    fn test_only_matching_skips_empty_matches() {
+        let dir = scratch_dir(&[("a.txt", "xyz\n")]);
+        let output = run_grep(&dir, &["-o", "-E", "a*", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "");
+        assert_eq!(output.status.code(), Some(0));
+    }
+}
+
+#[cfg(test)]
+mod tests_arguments {
+    use super::*;
+
+    #[test]
+    fn test_flags_after_operands() {
+        let dir = scratch_dir(&[("tree/a.txt", "pear\nplum\n")]);
+        let output = run_grep(&dir, &["pe\\w+", "tree", "-r", "-E"], "");
+        assert_eq!(stdout_of(&output), "tree/a.txt:pear\n");
+    }
+
+    #[test]
+    fn test_clustered_short_flags() {
+        let dir = scratch_dir(&[("tree/a.txt", "pear\n")]);
+        let output = run_grep(&dir, &["-roE", "e\\w", "tree"], "");
+        assert_eq!(stdout_of(&output), "tree/a.txt:ea\n");
+    }
+
+    #[test]
+    fn test_long_options() {
+        let dir = scratch_dir(&[("tree/a.txt", "pear\n")]);
+        let output = run_grep(&dir, &["--recursive", "--only-matching", "--regexp=ea", "tree"], "");
+        assert_eq!(stdout_of(&output), "tree/a.txt:ea\n");
+    }
+
+    #[test]
+    fn test_repeated_regexp_option() {
+        let dir = scratch_dir(&[("a.txt", "apple\nbanana\ncherry\n")]);
+        let output = run_grep(&dir, &["-e", "apple", "-eche", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "apple\ncherry\n");
+    }
+
+    #[test]
+    fn test_patterns_from_file() {
+        let dir = scratch_dir(&[("patterns", "^b\nrr\n"), ("a.txt", "apple\nbanana\ncherry\n")]);
+        let output = run_grep(&dir, &["-f", "patterns", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "banana\ncherry\n");
+    }
+
+    #[test]
+    fn test_double_dash_ends_options() {
+        let dir = scratch_dir(&[("a.txt", "x -v y\n")]);
+        let output = run_grep(&dir, &["--", "-v", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "x -v y\n");
+    }
+
+    #[test]
+    fn test_unknown_option_exits_with_two() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-Z", "pat"], "");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("invalid option -- 'Z'"));
+    }
+
+    #[test]
+    fn test_help_and_version() {
+        let dir = scratch_dir(&[]);
+        let help = run_grep(&dir, &["--help"], "");
+        assert_eq!(help.status.code(), Some(0));
+        assert!(stdout_of(&help).contains("--only-matching"));
+        let version = run_grep(&dir, &["-V"], "");
+        assert_eq!(stdout_of(&version), format!("codecrafters-grep {}\n", env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn test_pattern_error_caret_points_into_second_pattern() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-e", "ok", "-e", "ab[c", "x"], "");
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "grep: unclosed character class\n  ab[c\n    ^\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_stdin {
+    use super::*;
+
+    #[test]
+    fn test_stdin_all_lines_are_searched() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-E", "\\d"], "a1\nb\nc2\n");
+        assert_eq!(stdout_of(&output), "a1\nc2\n");
+        assert_eq!(output.status.code(), Some(0));
+    }
+
+    #[test]
+    fn test_stdin_no_match_exits_with_one() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-E", "\\d"], "a\nb\n");
+        assert_eq!(stdout_of(&output), "");
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    #[test]
+    fn test_stdin_without_trailing_newline() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-E", "end$"], "the end");
+        assert_eq!(stdout_of(&output), "the end\n");
+    }
+
+    #[test]
+    fn test_dash_names_stdin_among_files() {
+        let dir = scratch_dir(&[("a.txt", "pear\n")]);
+        let output = run_grep(&dir, &["-E", "pe", "a.txt", "-"], "peach\nplum\n");
+        assert_eq!(stdout_of(&output), "a.txt:pear\n(standard input):peach\n");
+    }
+
+    #[test]
+    fn test_stdin_honors_only_matching() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-o", "-E", "\\d+"], "a 12 b 3\nnone\n");
+        assert_eq!(stdout_of(&output), "12\n3\n");
+    }
+
+    #[test]
+    fn test_recursive_without_operands_searches_working_directory() {
+        let dir = scratch_dir(&[("sub/a.txt", "pear\n")]);
+        let output = run_grep(&dir, &["-r", "pear"], "pear\n");
+        assert_eq!(stdout_of(&output), "sub/a.txt:pear\n");
+    }
+}
+
+#[cfg(test)]
+mod tests_ignore_case {
+    use super::*;
+
+    #[test]
+    fn test_ignore_case_flag() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-i", "error"], "ERROR disk\nerror net\nwarn\n");
+        assert_eq!(stdout_of(&output), "ERROR disk\nerror net\n");
+    }
+
+    #[test]
+    fn test_ignore_case_long_option_with_only_matching() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["--ignore-case", "-o", "ab+"], "xABbBy\n");
+        assert_eq!(stdout_of(&output), "ABbB\n");
+    }
+}
+
+#[cfg(test)]
+mod tests_invert_and_count {
+    use super::*;
+
+    #[test]
+    fn test_invert_match() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-v", "noise"], "noise 1\nsignal\nnoise 2\n");
+        assert_eq!(stdout_of(&output), "signal\n");
+        assert_eq!(output.status.code(), Some(0));
+    }
+
+    #[test]
+    fn test_invert_match_nothing_selected() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-v", "a"], "a\nba\n");
+        assert_eq!(stdout_of(&output), "");
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    #[test]
+    fn test_count_single_file() {
+        let dir = scratch_dir(&[("a.txt", "hit\nmiss\nhit again\n")]);
+        let output = run_grep(&dir, &["-c", "hit", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "2\n");
+    }
+
+    #[test]
+    fn test_count_zero_exits_with_one() {
+        let dir = scratch_dir(&[("a.txt", "miss\n")]);
+        let output = run_grep(&dir, &["-c", "hit", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "0\n");
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    #[test]
+    fn test_count_multiple_files_with_prefix() {
+        let dir = scratch_dir(&[("a.txt", "hit\n"), ("b.txt", "miss\n")]);
+        let output = run_grep(&dir, &["-c", "hit", "a.txt", "b.txt"], "");
+        assert_eq!(stdout_of(&output), "a.txt:1\nb.txt:0\n");
+        assert_eq!(output.status.code(), Some(0));
+    }
+
+    #[test]
+    fn test_inverted_count_recursive() {
+        let dir = scratch_dir(&[("tree/a.txt", "hit\nmiss\nmiss\n")]);
+        let output = run_grep(&dir, &["-rvc", "hit", "tree"], "");
+        assert_eq!(stdout_of(&output), "tree/a.txt:2\n");
+    }
+
+    #[test]
+    fn test_count_with_only_matching_counts_lines() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-co", "a"], "aaa\nb\na\n");
+        assert_eq!(stdout_of(&output), "2\n");
+    }
+}
+
+#[cfg(test)]
+mod tests_line_numbers_and_offsets {
+    use super::*;
+
+    #[test]
+    fn test_line_numbers() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-n", "o"], "one\ntwo\nthree\nfour\n");
+        assert_eq!(stdout_of(&output), "1:one\n2:two\n4:four\n");
+    }
+
+    #[test]
+    fn test_line_numbers_with_path() {
+        let dir = scratch_dir(&[("a.txt", "x\nhit\n"), ("b.txt", "hit\n")]);
+        let output = run_grep(&dir, &["-n", "hit", "a.txt", "b.txt"], "");
+        assert_eq!(stdout_of(&output), "a.txt:2:hit\nb.txt:1:hit\n");
+    }
+
+    #[test]
+    fn test_byte_offsets_of_lines() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-b", "\\d"], "ab\ncd 12\nx12 y3\n");
+        assert_eq!(stdout_of(&output), "3:cd 12\n9:x12 y3\n");
+    }
+
+    #[test]
+    fn test_byte_offsets_of_matches_with_only_matching() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-nbo", "\\d+"], "ab\nx12 y3\n");
+        assert_eq!(stdout_of(&output), "2:4:12\n2:8:3\n");
+    }
+
+    #[test]
+    fn test_byte_offsets_count_multibyte_chars_and_crlf() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-b", "z"], "\u{e9}\u{e9}\r\nz\n");
+        assert_eq!(stdout_of(&output), "6:z\n");
+    }
+}
+
+#[cfg(test)]
+mod tests_context {
+    use super::*;
+
+    fn numbers(n: usize) -> String {
+        (1..=n).map(|i| format!("{}\n", i)).collect()
+    }
+
+    #[test]
+    fn test_after_context() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-A", "1", "^3$"], &numbers(6));
+        assert_eq!(stdout_of(&output), "3\n4\n");
+    }
+
+    #[test]
+    fn test_before_context_attached_value() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-B2", "^3$"], &numbers(6));
+        assert_eq!(stdout_of(&output), "1\n2\n3\n");
+    }
+
+    #[test]
+    fn test_context_prefixes_and_separator() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-n", "-C", "1", "-e", "^2$", "-e", "^8$"], &numbers(10));
+        assert_eq!(stdout_of(&output), "1-1\n2:2\n3-3\n--\n7-7\n8:8\n9-9\n");
+    }
+
+    #[test]
+    fn test_overlapping_context_is_merged() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-n", "-C", "2", "-e", "^3$", "-e", "^6$"], &numbers(10));
+        assert_eq!(stdout_of(&output), "1-1\n2-2\n3:3\n4-4\n5-5\n6:6\n7-7\n8-8\n");
+    }
+
+    #[test]
+    fn test_adjacent_groups_have_no_separator() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-A", "1", "-e", "^2$", "-e", "^4$"], &numbers(6));
+        assert_eq!(stdout_of(&output), "2\n3\n4\n5\n");
+    }
+
+    #[test]
+    fn test_explicit_after_wins_over_context() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-A", "0", "-C", "1", "^3$"], &numbers(6));
+        assert_eq!(stdout_of(&output), "2\n3\n");
+    }
+
+    #[test]
+    fn test_context_with_path_prefix_across_files() {
+        let dir = scratch_dir(&[("a.txt", "x\nhit\n"), ("b.txt", "hit\ny\n")]);
+        let output = run_grep(&dir, &["-C", "1", "hit", "a.txt", "b.txt"], "");
+        assert_eq!(stdout_of(&output), "a.txt-x\na.txt:hit\n--\nb.txt:hit\nb.txt-y\n");
+    }
+
+    #[test]
+    fn test_invalid_context_length() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-A", "x", "a"], "");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("x: invalid context length argument"));
+    }
+}
+
+#[cfg(test)]
+mod tests_color {
+    use super::*;
+
+    #[test]
+    fn test_color_always_highlights_each_match() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["--color=always", "an"], "banana\n");
+        assert_eq!(
+            stdout_of(&output),
+            "b\x1b[01;31m\x1b[Kan\x1b[m\x1b[K\x1b[01;31m\x1b[Kan\x1b[m\x1b[Ka\n"
+        );
+    }
+
+    #[test]
+    fn test_color_prefix_fields_and_separators() {
+        let dir = scratch_dir(&[("a.txt", "x\nhit\n"), ("b.txt", "hit\n")]);
+        let output = run_grep_with_env(
+            &dir,
+            &["--color=always", "-n", "-B", "1", "hit", "a.txt", "b.txt"],
+            "",
+            &[("GREP_COLORS", "ms=:fn=34:ln=33:se=")],
+        );
+        assert_eq!(
+            stdout_of(&output),
+            "\x1b[34m\x1b[Ka.txt\x1b[m\x1b[K-\x1b[33m\x1b[K1\x1b[m\x1b[K-x\n\
+             \x1b[34m\x1b[Ka.txt\x1b[m\x1b[K:\x1b[33m\x1b[K2\x1b[m\x1b[K:hit\n\
+             --\n\
+             \x1b[34m\x1b[Kb.txt\x1b[m\x1b[K:\x1b[33m\x1b[K1\x1b[m\x1b[K:hit\n"
+        );
+    }
+
+    #[test]
+    fn test_color_auto_is_plain_when_piped() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["--color", "an"], "banana\n");
+        assert_eq!(stdout_of(&output), "banana\n");
+    }
+
+    #[test]
+    fn test_color_never_ignores_grep_colors() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep_with_env(&dir, &["--color=never", "an"], "banana\n", &[("GREP_COLORS", "ms=32")]);
+        assert_eq!(stdout_of(&output), "banana\n");
+    }
+
+    #[test]
+    fn test_inverted_lines_are_not_highlighted() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep_with_env(&dir, &["--color=always", "-v", "x"], "banana\n", &[("GREP_COLORS", "ms=32")]);
+        assert_eq!(stdout_of(&output), "banana\n");
+    }
+
+    #[test]
+    fn test_inverted_context_lines_use_the_context_match_color() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep_with_env(
+            &dir,
+            &["--color=always", "-v", "-A", "1", "an"],
+            "kiwi\nbanana\n",
+            &[("GREP_COLORS", "ms=32:mc=35:se=")],
+        );
+        assert_eq!(stdout_of(&output), "kiwi\nb\x1b[35m\x1b[Kan\x1b[m\x1b[K\x1b[35m\x1b[Kan\x1b[m\x1b[Ka\n");
+    }
+
+    #[test]
+    fn test_invalid_color_argument() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["--color=sometimes", "an"], "banana\n");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("invalid argument 'sometimes' for '--color'"));
+    }
+}
+
+#[cfg(test)]
+mod tests_errors {
+    use super::*;
+
+    #[test]
+    fn test_missing_file_is_reported_and_skipped() {
+        let dir = scratch_dir(&[("a.txt", "hit\n")]);
+        let output = run_grep(&dir, &["hit", "missing.txt", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "a.txt:hit\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "grep: missing.txt: No such file or directory\n");
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_directory_without_recursive_is_reported() {
+        let dir = scratch_dir(&[("sub/a.txt", "hit\n"), ("b.txt", "hit\n")]);
+        let output = run_grep(&dir, &["hit", "sub", "b.txt"], "");
+        assert_eq!(stdout_of(&output), "b.txt:hit\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "grep: sub: Is a directory\n");
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_no_messages_suppresses_errors_but_not_status() {
+        let dir = scratch_dir(&[("a.txt", "hit\n")]);
+        let output = run_grep(&dir, &["-s", "hit", "missing.txt", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "a.txt:hit\n");
+        assert!(output.stderr.is_empty());
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_closed_output_ends_the_run_quietly() {
+        // far more output than a pipe holds
+        let contents = "hit\n".repeat(20_000);
+        let files: Vec<(String, &str)> = (0..50).map(|index| (format!("f{}.txt", index), contents.as_str())).collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(name, contents)| (name.as_str(), *contents)).collect();
+        let dir = scratch_dir(&files);
+        for args in [&["-r", "hit"][..], &["-r", "-j", "1", "hit"], &["-r", "-j", "4", "hit"]] {
+            let output = run_grep_into_closed_pipe(&dir, args, Stdio::null());
+            assert_eq!(String::from_utf8_lossy(&output.stderr), "", "{:?}", args);
+            assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        }
+        let stdin = fs::File::open(dir.join("f0.txt")).unwrap();
+        let output = run_grep_into_closed_pipe(&dir, &["hit"], Stdio::from(stdin));
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_write_error_is_reported_once() {
+        let files: Vec<(String, &str)> = (0..20).map(|index| (format!("f{}.txt", index), "hit\n")).collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(name, contents)| (name.as_str(), *contents)).collect();
+        let dir = scratch_dir(&files);
+        for jobs in ["1", "4"] {
+            let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-grep"))
+                .args(["-r", "-j", jobs, "hit"])
+                .current_dir(&dir)
+                .stdout(fs::File::create("/dev/full").unwrap())
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stderr), "grep: write error: No space left on device\n");
+            assert_eq!(output.status.code(), Some(2));
+        }
+    }
+
+    #[test]
+    fn test_error_without_matches_exits_two() {
+        let dir = scratch_dir(&[("a.txt", "miss\n")]);
+        let output = run_grep(&dir, &["hit", "a.txt", "missing.txt"], "");
+        assert_eq!(output.status.code(), Some(2));
+    }
+}
+
+#[cfg(test)]
+mod tests_binary {
+    use super::*;
+
+    #[test]
+    fn test_binary_file_match_message() {
+        let dir = scratch_dir(&[("data.bin", "header\0\nhit here\nhit again\n")]);
+        let output = run_grep(&dir, &["hit", "data.bin"], "");
+        assert_eq!(stdout_of(&output), "Binary file data.bin matches\n");
+        assert_eq!(output.status.code(), Some(0));
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_binary_and_printed_raw_with_text() {
+        let dir = scratch_dir(&[]);
+        fs::write(dir.join("latin1.txt"), b"caf\xe9 hit\nplain\n").unwrap();
+        let output = run_grep(&dir, &["hit", "latin1.txt"], "");
+        assert_eq!(stdout_of(&output), "Binary file latin1.txt matches\n");
+        let output = run_grep(&dir, &["-a", "hit", "latin1.txt"], "");
+        assert_eq!(output.stdout, b"caf\xe9 hit\n");
+    }
+
+    #[test]
+    fn test_only_matching_keeps_raw_bytes_and_offsets() {
+        let dir = scratch_dir(&[]);
+        fs::write(dir.join("latin1.txt"), b"\xe9t\xe9 hit\n").unwrap();
+        let output = run_grep(&dir, &["-a", "-o", "-b", "hit", "latin1.txt"], "");
+        assert_eq!(stdout_of(&output), "4:hit\n");
+    }
+
+    #[test]
+    fn test_binary_stdin_is_named() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["hit"], "\0hit\n");
+        assert_eq!(stdout_of(&output), "Binary file (standard input) matches\n");
+    }
+
+    #[test]
+    fn test_without_match_skips_binary_files() {
+        let dir = scratch_dir(&[("data.bin", "\0hit\n"), ("a.txt", "hit\n")]);
+        let output = run_grep(&dir, &["-I", "hit", "data.bin", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "a.txt:hit\n");
+        let output = run_grep(&dir, &["--binary-files=without-match", "hit", "data.bin"], "");
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    #[test]
+    fn test_count_in_binary_file() {
+        let dir = scratch_dir(&[("data.bin", "\0hit\nhit\nmiss\n")]);
+        let output = run_grep(&dir, &["-c", "hit", "data.bin"], "");
+        assert_eq!(stdout_of(&output), "2\n");
+    }
+
+    #[test]
+    fn test_invalid_binary_files_argument() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["--binary-files=maybe", "hit"], "");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("invalid argument 'maybe' for '--binary-files'"));
+    }
+}
+
+#[cfg(test)]
+mod tests_ignore {
+    use super::*;
+
+    fn sorted_lines(output: &Output) -> Vec<String> {
+        let mut lines: Vec<String> = stdout_of(output).lines().map(String::from).collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn test_gitignore_is_honored() {
+        let dir = scratch_dir(&[
+            (".gitignore", "target/\n*.log\n"),
+            ("src/main.rs", "hit\n"),
+            ("target/debug/out.rs", "hit\n"),
+            ("build.log", "hit\n"),
+        ]);
+        let output = run_grep(&dir, &["-r", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["src/main.rs:hit"]);
+    }
+
+    #[test]
+    fn test_negation_and_nested_ignore_files() {
+        let dir = scratch_dir(&[
+            (".gitignore", "*.txt\n"),
+            ("keep/.ignore", "!important.txt\n"),
+            ("keep/important.txt", "hit\n"),
+            ("keep/other.txt", "hit\n"),
+            ("a.txt", "hit\n"),
+        ]);
+        let output = run_grep(&dir, &["-r", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["keep/important.txt:hit"]);
+    }
+
+    #[test]
+    fn test_anchored_pattern_only_matches_at_its_level() {
+        let dir = scratch_dir(&[
+            (".gitignore", "/notes.md\n"),
+            ("notes.md", "hit\n"),
+            ("docs/notes.md", "hit\n"),
+        ]);
+        let output = run_grep(&dir, &["-r", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["docs/notes.md:hit"]);
+    }
+
+    #[test]
+    fn test_git_info_exclude() {
+        let dir = scratch_dir(&[(".git/info/exclude", "secret.txt\n"), ("secret.txt", "hit\n"), ("a.txt", "hit\n")]);
+        let output = run_grep(&dir, &["-r", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["a.txt:hit"]);
+    }
+
+    #[test]
+    fn test_hidden_files_are_skipped_unless_asked() {
+        let dir = scratch_dir(&[(".env", "hit\n"), ("a.txt", "hit\n")]);
+        let output = run_grep(&dir, &["-r", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["a.txt:hit"]);
+        let output = run_grep(&dir, &["-r", "--hidden", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec![".env:hit", "a.txt:hit"]);
+    }
+
+    #[test]
+    fn test_no_ignore() {
+        let dir = scratch_dir(&[(".gitignore", "*.log\n"), ("build.log", "hit\n")]);
+        let output = run_grep(&dir, &["-r", "--no-ignore", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["build.log:hit"]);
+    }
+
+    #[test]
+    fn test_ignore_files_above_the_operand_apply() {
+        let dir = scratch_dir(&[
+            (".git/HEAD", "ref: refs/heads/main\n"),
+            (".gitignore", "**/deep\n"),
+            ("sub/deep/z.txt", "hit\n"),
+            ("sub/a.txt", "hit\n"),
+        ]);
+        let output = run_grep(&dir.join("sub"), &["-r", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["a.txt:hit"]);
+        let output = run_grep(&dir, &["-r", "hit", "sub"], "");
+        assert_eq!(sorted_lines(&output), vec!["sub/a.txt:hit"]);
+    }
+
+    #[test]
+    fn test_explicit_operands_are_never_ignored() {
+        let dir = scratch_dir(&[(".gitignore", "*.log\n"), ("build.log", "hit\n")]);
+        let output = run_grep(&dir, &["-r", "hit", "build.log"], "");
+        assert_eq!(stdout_of(&output), "build.log:hit\n");
+    }
+}
+
+#[cfg(test)]
+mod tests_filters {
+    use super::*;
+
+    fn sorted_lines(output: &Output) -> Vec<String> {
+        let mut lines: Vec<String> = stdout_of(output).lines().map(String::from).collect();
+        lines.sort();
+        lines
+    }
+
+    fn tree() -> PathBuf {
+        scratch_dir(&[
+            ("src/main.rs", "hit\n"),
+            ("src/lib.rs", "hit\n"),
+            ("docs/guide.md", "hit\n"),
+            ("vendor/dep/lib.rs", "hit\n"),
+            ("Cargo.toml", "hit\n"),
+        ])
+    }
+
+    #[test]
+    fn test_include() {
+        let output = run_grep(&tree(), &["-r", "--include=*.{rs,toml}", "hit"], "");
+        assert_eq!(
+            sorted_lines(&output),
+            vec!["Cargo.toml:hit", "src/lib.rs:hit", "src/main.rs:hit", "vendor/dep/lib.rs:hit"]
+        );
+    }
+
+    #[test]
+    fn test_exclude_and_exclude_dir() {
+        let output = run_grep(&tree(), &["-r", "--exclude=*.md", "--exclude-dir=vendor", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["Cargo.toml:hit", "src/lib.rs:hit", "src/main.rs:hit"]);
+    }
+
+    #[test]
+    fn test_filters_apply_to_operands() {
+        let dir = scratch_dir(&[("logs/a.log", "hit\n"), ("logs/b.txt", "hit\n"), ("vendor/c.txt", "hit\n")]);
+        let output = run_grep(&dir, &["-r", "--exclude=*.log", "hit", "logs/a.log", "logs/b.txt"], "");
+        assert_eq!(stdout_of(&output), "logs/b.txt:hit\n");
+        let output = run_grep(&dir, &["--include=*.log", "hit", "logs/a.log", "logs/b.txt"], "");
+        assert_eq!(stdout_of(&output), "logs/a.log:hit\n");
+        let output = run_grep(&dir, &["-r", "--exclude-dir=vendor", "hit", "vendor", "logs"], "");
+        assert_eq!(sorted_lines(&output), vec!["logs/a.log:hit", "logs/b.txt:hit"]);
+    }
+
+    #[test]
+    fn test_glob_selects_and_negates() {
+        let output = run_grep(&tree(), &["-r", "-g", "*.rs", "-g", "!vendor/", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["src/lib.rs:hit", "src/main.rs:hit"]);
+    }
+
+    #[test]
+    fn test_glob_with_path_is_relative_to_operand() {
+        let output = run_grep(&tree(), &["-r", "-g", "src/*.rs", "hit", "."], "");
+        assert_eq!(sorted_lines(&output), vec!["./src/lib.rs:hit", "./src/main.rs:hit"]);
+    }
+
+    #[test]
+    fn test_glob_overrides_ignore_files() {
+        let dir = scratch_dir(&[(".gitignore", "*.log\n"), ("build.log", "hit\n")]);
+        let output = run_grep(&dir, &["-r", "-g", "*.log", "hit"], "");
+        assert_eq!(sorted_lines(&output), vec!["build.log:hit"]);
+    }
+
+    #[test]
+    fn test_invalid_glob() {
+        let output = run_grep(&tree(), &["-r", "--include=[a-", "hit"], "");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("invalid glob '[a-': unclosed character class"));
+    }
+}
+
+#[cfg(test)]
+mod tests_traversal {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_sorted_depth_first_order() {
+        let dir = scratch_dir(&[
+            ("b.txt", "hit\n"),
+            ("a/z.txt", "hit\n"),
+            ("a/sub/y.txt", "hit\n"),
+            ("c/x.txt", "hit\n"),
+            ("a.txt", "hit\n"),
+        ]);
+        let output = run_grep(&dir, &["-r", "hit"], "");
+        assert_eq!(
+            stdout_of(&output),
+            "a/sub/y.txt:hit\na/z.txt:hit\na.txt:hit\nb.txt:hit\nc/x.txt:hit\n"
+        );
+    }
+
+    #[test]
+    fn test_sort_by_size() {
+        let dir = scratch_dir(&[("a.txt", "hit hit hit\n"), ("b.txt", "hit\n"), ("c.txt", "hit hit\n")]);
+        let output = run_grep(&dir, &["-r", "--sort=size", "-c", "hit"], "");
+        assert_eq!(stdout_of(&output), "b.txt:1\nc.txt:1\na.txt:1\n");
+    }
+
+    #[test]
+    fn test_sort_by_modified() {
+        let dir = scratch_dir(&[("a.txt", "hit\n"), ("b.txt", "hit\n")]);
+        let older = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options().write(true).open(dir.join("b.txt")).unwrap().set_modified(older).unwrap();
+        let output = run_grep(&dir, &["-r", "--sort=modified", "hit"], "");
+        assert_eq!(stdout_of(&output), "b.txt:hit\na.txt:hit\n");
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let dir = scratch_dir(&[("top.txt", "hit\n"), ("a/mid.txt", "hit\n"), ("a/b/deep.txt", "hit\n")]);
+        let output = run_grep(&dir, &["-r", "--max-depth=2", "hit"], "");
+        assert_eq!(stdout_of(&output), "a/mid.txt:hit\ntop.txt:hit\n");
+        let output = run_grep(&dir, &["-r", "--max-depth", "0", "hit"], "");
+        assert_eq!(stdout_of(&output), "");
+    }
+
+    #[test]
+    fn test_invalid_sort() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-r", "--sort=random", "hit"], "");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("invalid argument 'random' for '--sort'"));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests_symlinks {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_recursive_skips_symlinks() {
+        let dir = scratch_dir(&[("real/a.txt", "hit\n"), ("outside/b.txt", "hit\n")]);
+        symlink(dir.join("outside/b.txt"), dir.join("real/link.txt")).unwrap();
+        let output = run_grep(&dir, &["-r", "hit", "real"], "");
+        assert_eq!(stdout_of(&output), "real/a.txt:hit\n");
+    }
+
+    #[test]
+    fn test_follow_searches_through_symlinks() {
+        let dir = scratch_dir(&[("real/a.txt", "hit\n"), ("outside/b.txt", "hit\n")]);
+        symlink(dir.join("outside"), dir.join("real/linked")).unwrap();
+        let output = run_grep(&dir, &["-R", "hit", "real"], "");
+        assert_eq!(stdout_of(&output), "real/a.txt:hit\nreal/linked/b.txt:hit\n");
+    }
+
+    #[test]
+    fn test_symlink_operand_is_followed() {
+        let dir = scratch_dir(&[("outside/b.txt", "hit\n")]);
+        symlink(dir.join("outside"), dir.join("link")).unwrap();
+        let output = run_grep(&dir, &["-r", "hit", "link"], "");
+        assert_eq!(stdout_of(&output), "link/b.txt:hit\n");
+    }
+
+    #[test]
+    fn test_symlink_loop_is_reported() {
+        let dir = scratch_dir(&[("tree/a.txt", "hit\n")]);
+        symlink(dir.join("tree"), dir.join("tree/loop")).unwrap();
+        let output = run_grep(&dir, &["--follow", "hit", "tree"], "");
+        assert_eq!(stdout_of(&output), "tree/a.txt:hit\n");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "grep: tree/loop: warning: recursive directory loop\n"
+        );
+        assert_eq!(output.status.code(), Some(0));
+        let output = run_grep(&dir, &["--follow", "-s", "miss", "tree"], "");
+        assert!(output.stderr.is_empty());
+        assert_eq!(output.status.code(), Some(1));
+    }
+}
+
+#[cfg(test)]
+mod tests_parallel {
+    use super::*;
+
+    fn many_files() -> PathBuf {
+        let files: Vec<(String, String)> = (0..60)
+            .map(|index| {
+                let contents = (0..50).map(|line| format!("file {} line {} hit\n", index, line)).collect();
+                (format!("d{}/f{:02}.txt", index % 7, index), contents)
+            })
+            .collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(path, contents)| (path.as_str(), contents.as_str())).collect();
+        scratch_dir(&files)
+    }
+
+    #[test]
+    fn test_parallel_output_matches_sequential() {
+        let dir = many_files();
+        let sequential = run_grep(&dir, &["-r", "-j", "1", "-n", "hit"], "");
+        let parallel = run_grep(&dir, &["-r", "-j", "8", "-n", "hit"], "");
+        assert_eq!(stdout_of(&sequential).lines().count(), 3000);
+        assert_eq!(stdout_of(&parallel), stdout_of(&sequential));
+    }
+
+    #[test]
+    fn test_parallel_context_separators_between_files() {
+        let dir = scratch_dir(&[("a.txt", "x\nhit\n"), ("b.txt", "y\n"), ("c.txt", "hit\nz\n")]);
+        let output = run_grep(&dir, &["-j", "3", "-C", "1", "hit", "a.txt", "b.txt", "c.txt"], "");
+        assert_eq!(stdout_of(&output), "a.txt-x\na.txt:hit\n--\nc.txt:hit\nc.txt-z\n");
+    }
+
+    #[test]
+    fn test_parallel_errors_are_reported_in_order() {
+        let dir = scratch_dir(&[("a.txt", "hit\n")]);
+        let output = run_grep(&dir, &["-j", "4", "hit", "missing.txt", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "a.txt:hit\n");
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "grep: missing.txt: No such file or directory\n");
+        assert_eq!(output.status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_invalid_thread_count() {
+        let dir = scratch_dir(&[]);
+        let output = run_grep(&dir, &["-j", "many", "hit"], "");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("many: invalid number of threads"));
+    }
+}
+
+#[cfg(test)]
+mod tests_large_files {
+    use super::*;
+
+    // big enough to be memory-mapped, with a hit on every 1000th line
+    fn large_text(line_ending: &str) -> String {
+        (0..20_000)
+            .map(|line| {
+                let word = if line % 1000 == 7 { "hit" } else { "miss" };
+                format!("line {} {}{}", line, word, line_ending)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_large_file_matches_stdin_output() {
+        let text = large_text("\n");
+        let dir = scratch_dir(&[("big.txt", &text)]);
+        let runs: [&[&str]; 6] = [
+            &["-n", "-b", "hit$"],
+            &["-c", "hit"],
+            &["-o", "-n", "hit"],
+            &["-v", "-c", "hit"],
+            &["-n", "-C", "1", "hit"],
+            &["-n", "^line 7 "],
+        ];
+        for args in runs {
+            let from_file = run_grep(&dir, &[args, &["big.txt"]].concat(), "");
+            let from_stdin = run_grep(&dir, args, &text);
+            assert_eq!(stdout_of(&from_file), stdout_of(&from_stdin), "{:?}", args);
+        }
+        let output = run_grep(&dir, &["-n", "-b", "hit", "big.txt"], "");
+        assert_eq!(stdout_of(&output).lines().count(), 20);
+        assert!(stdout_of(&output).starts_with("8:84:line 7 hit\n1008:13994:line 1007 hit\n"));
+    }
+
+    #[test]
+    fn test_large_file_with_crlf_and_no_trailing_newline() {
+        let mut text = large_text("\r\n");
+        text.push_str("last hit");
+        let dir = scratch_dir(&[("big.txt", &text)]);
+        let output = run_grep(&dir, &["-n", "hit$", "big.txt"], "");
+        let stdout = stdout_of(&output);
+        assert_eq!(stdout.lines().count(), 21);
+        assert!(stdout.starts_with("8:line 7 hit\n"));
+        assert!(stdout.ends_with("20001:last hit\n"));
+    }
+
+    #[test]
+    fn test_large_file_empty_lines() {
+        let text = format!("{}\n{}", large_text("\n"), large_text("\n"));
+        let dir = scratch_dir(&[("big.txt", &text)]);
+        let output = run_grep(&dir, &["-n", "^$", "big.txt"], "");
+        assert_eq!(stdout_of(&output), "20001:\n");
+    }
+
+    #[test]
+    fn test_large_binary_file() {
+        let text = format!("\0{}", large_text("\n"));
+        let dir = scratch_dir(&[("big.bin", &text)]);
+        let output = run_grep(&dir, &["hit", "big.bin"], "");
+        assert_eq!(stdout_of(&output), "Binary file big.bin matches\n");
+        let output = run_grep(&dir, &["-c", "hit", "big.bin"], "");
+        assert_eq!(stdout_of(&output), "20\n");
+    }
+}
+
+#[cfg(test)]
+mod tests_backtracking_limits {
+    use super::*;
+
+    #[test]
+    fn test_line_over_the_limits_is_reported() {
+        let text = format!("ok dd\n{}\nbb d\n", "x".repeat(40));
+        let dir = scratch_dir(&[("a.txt", &text)]);
+        let output = run_grep(&dir, &["-E", r"((x|xx)*)*\1[yz]|(b)\3 d|o(k) d", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "ok dd\nbb d\n");
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "grep: a.txt:2: warning: backtracking step budget exceeded; line taken as not matching\n"
+        );
+        let output = run_grep(&dir, &["-s", "-c", "-E", r"((x|xx)*)*\1[yz]|(b)\3 d|o(k) d", "a.txt"], "");
+        assert_eq!(stdout_of(&output), "2\n");
+        assert!(output.stderr.is_empty());
+    }
+
+    #[test]
+    fn test_long_lines_with_back_references_match() {
+        let text = format!(
+            "{} foo foo\nfoo foo {}\n{}x\n",
+            "w".repeat(1000),
+            "y".repeat(10_000),
+            "ab".repeat(10_000)
+        );
+        let dir = scratch_dir(&[("a.txt", &text), ("b.txt", &text)]);
+        for pattern in [r"^\w+ (\w+) \1$", r"^(\w+) \1.*y$", r"(ab|cd)+\1x"] {
+            let output = run_grep(&dir, &["-c", "-E", pattern, "a.txt"], "");
+            assert_eq!(stdout_of(&output), "1\n", "{}", pattern);
+            assert!(output.stderr.is_empty(), "{}", pattern);
+            // the same on the worker threads
+            let output = run_grep(&dir, &["-j", "2", "-c", "-E", pattern, "a.txt", "b.txt"], "");
+            assert_eq!(stdout_of(&output), "a.txt:1\nb.txt:1\n", "{}", pattern);
+            assert!(output.stderr.is_empty(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_large_file_line_over_the_limits_is_reported() {
+        let text: String = (0..20_000)
+            .map(|line| match line {
+                // deeper than even the CLI's recursion limit
+                1000 => format!("{}d\n", "a".repeat(100_000)),
+                line => format!("line {} bb d\n", line),
+            })
+            .collect();
+        let dir = scratch_dir(&[("big.txt", &text)]);
+        let output = run_grep(&dir, &["-c", "-E", r"(a|b)(a|b)*\1 ?d", "big.txt"], "");
+        assert_eq!(stdout_of(&output), "19999\n");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "grep: big.txt:1001: warning: backtracking recursion limit exceeded; line taken as not matching\n"
+        );
+    }
+}
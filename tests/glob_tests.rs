@@ -0,0 +1,98 @@
+use codecrafters_grep::error::GlobError;
+use codecrafters_grep::glob::Glob;
+
+fn matches(pattern: &str, path: &str) -> bool {
+    Glob::new(pattern).unwrap().is_match(path)
+}
+
+#[cfg(test)]
+mod tests_glob {
+    use super::*;
+
+    #[test]
+    fn test_literal() {
+        assert!(matches("main.rs", "main.rs"));
+        assert!(!matches("main.rs", "main.rsx"));
+    }
+
+    #[test]
+    fn test_star_stays_in_component() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", ".rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("src/*.rs", "src/main.rs"));
+    }
+
+    #[test]
+    fn test_question_mark() {
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "a/c"));
+        assert!(!matches("a?c", "ac"));
+    }
+
+    #[test]
+    fn test_class() {
+        assert!(matches("file[0-9].txt", "file7.txt"));
+        assert!(!matches("file[0-9].txt", "filex.txt"));
+        assert!(matches("file[!0-9].txt", "filex.txt"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+    }
+
+    #[test]
+    fn test_double_star_prefix() {
+        assert!(matches("**/foo", "foo"));
+        assert!(matches("**/foo", "a/b/foo"));
+        assert!(!matches("**/foo", "a/xfoo"));
+    }
+
+    #[test]
+    fn test_double_star_middle() {
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(!matches("a/**/b", "ab"));
+    }
+
+    #[test]
+    fn test_double_star_suffix() {
+        assert!(matches("target/**", "target/debug/grep"));
+        assert!(!matches("target/**", "targets/x"));
+    }
+
+    #[test]
+    fn test_double_star_inside_component_is_star() {
+        assert!(matches("a**z", "abcz"));
+        assert!(!matches("a**z", "a/z"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert!(matches("\\*.txt", "*.txt"));
+        assert!(!matches("\\*.txt", "a.txt"));
+    }
+
+    #[test]
+    fn test_alternates() {
+        assert!(matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(matches("*.{rs,toml}", "main.rs"));
+        assert!(!matches("*.{rs,toml}", "README.md"));
+        assert!(matches("{src,tests}/**/*.rs", "tests/cli_tests.rs"));
+    }
+
+    #[test]
+    fn test_nested_and_empty_alternates() {
+        assert!(matches("a{b,c{d,e}}", "ace"));
+        assert!(matches("log{,.1}", "log"));
+        assert!(matches("log{,.1}", "log.1"));
+    }
+
+    #[test]
+    fn test_unclosed_alternates() {
+        assert_eq!(Glob::new("*.{rs,md").unwrap_err(), GlobError::UnclosedAlternates { offset: 2 });
+    }
+
+    #[test]
+    fn test_unclosed_class() {
+        assert_eq!(Glob::new("ab[cd").unwrap_err(), GlobError::UnclosedClass { offset: 2 });
+    }
+}
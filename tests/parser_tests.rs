@@ -1,5 +1,6 @@
 use codecrafters_grep::parse::Parser;
 use codecrafters_grep::parse::QuantifierType;
+use codecrafters_grep::error::ParseError;
 
 #[cfg(test)]
 mod tests_internal_functions {
@@ -53,7 +54,7 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_slash_digit_escape() {
         let mut parser = Parser::new("\\dabc");
-        let slash = parser.parse_slash();
+        let slash = parser.parse_slash().unwrap();
         assert_eq!(slash, Some("\\d".to_string()));
         assert_eq!(parser.chars.into_iter().collect::<String>(), "abc"); // \d consumed
     }
@@ -61,23 +62,22 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_slash_no_backslash() {
         let mut parser = Parser::new("abc");
-        let slash = parser.parse_slash();
+        let slash = parser.parse_slash().unwrap();
         assert_eq!(slash, None);
         assert_eq!(parser.chars.into_iter().collect::<String>(), "abc"); // nothing consumed
     }
 
     #[test]
-    #[should_panic(expected = "Invalid escape sequence")]
     fn test_parse_slash_backslash_at_end() {
         let mut parser = Parser::new("\\");
-        let _ = parser.parse_slash(); // should panic
+        assert_eq!(parser.parse_slash(), Err(ParseError::TrailingBackslash { offset: 0 }));
     }
 
     // Tests for parse_char_class
     #[test]
     fn test_parse_char_class_basic() {
         let mut parser = Parser::new("[abc]+");
-        let cls = parser.parse_char_class();
+        let cls = parser.parse_char_class().unwrap();
         assert_eq!(cls, Some("[abc]".to_string()));
         assert_eq!(parser.chars.into_iter().collect::<String>(), "+");
     }
@@ -85,23 +85,22 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_char_class_negated() {
         let mut parser = Parser::new("[^xyz]end");
-        let cls = parser.parse_char_class();
+        let cls = parser.parse_char_class().unwrap();
         assert_eq!(cls, Some("[^xyz]".to_string()));
         assert_eq!(parser.chars.into_iter().collect::<String>(), "end");
     }
 
     #[test]
-    #[should_panic(expected = "Unclosed character class")]
     fn test_parse_char_class_unclosed() {
         let mut parser = Parser::new("[abc");
-        let _ = parser.parse_char_class(); // should panic
+        assert_eq!(parser.parse_char_class(), Err(ParseError::UnclosedCharClass { offset: 0 }));
     }
 
     // Tests for parse_quantifier
     #[test]
     fn test_parse_quantifier_plus() {
         let mut parser = Parser::new("+next");
-        let q = parser.parse_quantifier();
+        let q = parser.parse_quantifier().unwrap();
         assert_eq!(q, Some(QuantifierType::Plus));
         assert_eq!(parser.chars.into_iter().collect::<String>(), "next");
     }
@@ -109,7 +108,7 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_quantifier_question() {
         let mut parser = Parser::new("?abc");
-        let q = parser.parse_quantifier();
+        let q = parser.parse_quantifier().unwrap();
         assert_eq!(q, Some(QuantifierType::Question));
         assert_eq!(parser.chars.into_iter().collect::<String>(), "abc");
     }
@@ -117,7 +116,7 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_quantifier_star() {
         let mut parser = Parser::new("*bat");
-        let q = parser.parse_quantifier();
+        let q = parser.parse_quantifier().unwrap();
         assert_eq!(q, Some(QuantifierType::Star));
         assert_eq!(parser.chars.into_iter().collect::<String>(), "bat");
     }
@@ -125,7 +124,7 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_quantifier_none() {
         let mut parser = Parser::new("abc");
-        let q = parser.parse_quantifier();
+        let q = parser.parse_quantifier().unwrap();
         assert_eq!(q, None);
         assert_eq!(parser.chars.into_iter().collect::<String>(), "abc"); // unchanged
     }
@@ -134,7 +133,7 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_quantifier_exact_n() {
         let mut parser = Parser::new("{3}abc");
-        let q = parser.parse_quantifier();
+        let q = parser.parse_quantifier().unwrap();
         assert_eq!(q, Some(QuantifierType::Repitition((3, 3))));
         assert_eq!(parser.chars.into_iter().collect::<String>(), "abc");
     }
@@ -142,19 +141,68 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_quantifier_at_least_n() {
         let mut parser = Parser::new("{2,}def");
-        let q = parser.parse_quantifier();
-        assert_eq!(q, Some(QuantifierType::Repitition((2, i32::MAX))));
+        let q = parser.parse_quantifier().unwrap();
+        assert_eq!(q, Some(QuantifierType::Repitition((2, u32::MAX))));
         assert_eq!(parser.chars.into_iter().collect::<String>(), "def");
     }
 
     #[test]
     fn test_parse_quantifier_range_n_m() {
         let mut parser = Parser::new("{2,5}ghi");
-        let q = parser.parse_quantifier();
+        let q = parser.parse_quantifier().unwrap();
         assert_eq!(q, Some(QuantifierType::Repitition((2, 5))));
         assert_eq!(parser.chars.into_iter().collect::<String>(), "ghi");
     }
 
+    #[test]
+    fn test_parse_quantifier_at_most_m() {
+        let mut parser = Parser::new("{,4}");
+        let q = parser.parse_quantifier().unwrap();
+        assert_eq!(q, Some(QuantifierType::Repitition((0, 4))));
+    }
+
+    #[test]
+    fn test_parse_quantifier_invalid_repetition() {
+        let mut parser = Parser::new("{a,3}");
+        assert_eq!(
+            parser.parse_quantifier(),
+            Err(ParseError::InvalidRepetition { offset: 0, content: "a,3".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_parse_quantifier_negative_bounds() {
+        for (pattern, content) in [("{-2}", "-2"), ("{-1,3}", "-1,3"), ("{+3}", "+3")] {
+            let mut parser = Parser::new(pattern);
+            assert_eq!(
+                parser.parse_quantifier(),
+                Err(ParseError::InvalidRepetition { offset: 0, content: content.to_string() })
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_quantifier_count_too_large() {
+        let mut parser = Parser::new("{99999999999}");
+        assert!(matches!(parser.parse_quantifier(), Err(ParseError::InvalidRepetition { .. })));
+        let mut parser = Parser::new("{1,32768}");
+        assert!(matches!(parser.parse_quantifier(), Err(ParseError::InvalidRepetition { .. })));
+        let mut parser = Parser::new("{32767}");
+        assert_eq!(parser.parse_quantifier(), Ok(Some(QuantifierType::Repitition((32767, 32767)))));
+    }
+
+    #[test]
+    fn test_parse_quantifier_reversed_bounds() {
+        let mut parser = Parser::new("{5,2}");
+        assert!(matches!(parser.parse_quantifier(), Err(ParseError::InvalidRepetition { .. })));
+    }
+
+    #[test]
+    fn test_parse_quantifier_unclosed_repetition() {
+        let mut parser = Parser::new("{3");
+        assert_eq!(parser.parse_quantifier(), Err(ParseError::UnclosedRepetition { offset: 0 }));
+    }
+
     // Tests for parse_start_anchor
     #[test]
     fn test_parse_start_anchor_when_present() {
@@ -221,7 +269,7 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_parentheses_basic() {
         let mut parser = Parser::new("(cat|dog)+");
-        let group = parser.parse_parentheses();
+        let group = parser.parse_parentheses().unwrap();
         assert_eq!(group, Some(("(cat|dog)".to_string(), false)));
         assert_eq!(parser.peek(), Some('+'));
     }
@@ -229,16 +277,15 @@ mod tests_internal_functions {
     #[test]
     fn test_parse_parentheses_nested() {
         let mut parser = Parser::new("(a|(b|c))end");
-        let group = parser.parse_parentheses();
+        let group = parser.parse_parentheses().unwrap();
         assert_eq!(group, Some(("(a|(b|c))".to_string(), false)));
         assert_eq!(parser.peek(), Some('e'));
     }
 
     #[test]
-    #[should_panic(expected = "Unclosed parentheses")]
     fn test_parse_parentheses_unclosed() {
         let mut parser = Parser::new("(abc");
-        let _ = parser.parse_parentheses();
+        assert_eq!(parser.parse_parentheses(), Err(ParseError::UnclosedParenthesis { offset: 0 }));
     }
 
     // Tests for split_alternatives
@@ -0,0 +1,737 @@
+use codecrafters_grep::ast::{CharClass, ClassItem, Node};
+use codecrafters_grep::error::ParseError;
+use codecrafters_grep::{Error, Regex};
+
+#[cfg(test)]
+mod tests_regex_compile {
+    use super::*;
+
+    // ============================================================================
+    // Regex::new Tests
+    // ============================================================================
+
+    #[test]
+    fn test_ast_literal_with_quantifier() {
+        let regex = Regex::new("ca+t").unwrap();
+        assert_eq!(
+            regex.ast(),
+            &Node::Concat(vec![
+                Node::Literal("c".to_string()),
+                Node::Repeat { node: Box::new(Node::Literal("a".to_string())), min: 1, max: None },
+                Node::Literal("t".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ast_groups_are_numbered_by_open_paren() {
+        let regex = Regex::new("((a)(b))").unwrap();
+        assert_eq!(regex.group_count(), 3);
+        assert_eq!(
+            regex.ast(),
+            &Node::Group {
+                index: 1,
+                node: Box::new(Node::Concat(vec![
+                    Node::Group { index: 2, node: Box::new(Node::Literal("a".to_string())) },
+                    Node::Group { index: 3, node: Box::new(Node::Literal("b".to_string())) },
+                ])),
+            }
+        );
+    }
+
+    #[test]
+    fn test_ast_char_class_range() {
+        let regex = Regex::new("[^a-z_]").unwrap();
+        assert_eq!(
+            regex.ast(),
+            &Node::Class(CharClass::new(vec![ClassItem::Range('a', 'z'), ClassItem::Char('_')], true))
+        );
+    }
+
+    #[test]
+    fn test_unmatched_closing_parenthesis() {
+        assert_eq!(
+            Regex::new("ab)").unwrap_err(),
+            Error::Parse(ParseError::UnmatchedParenthesis { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn test_backreference_to_missing_group() {
+        assert_eq!(
+            Regex::new("(a)\\2").unwrap_err(),
+            Error::Parse(ParseError::InvalidBackreference { offset: 3, index: 2 })
+        );
+    }
+
+    #[test]
+    fn test_error_offset_inside_group() {
+        let err = Regex::new("ab(c(d)\\3)").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::InvalidBackreference { offset: 7, index: 3 }));
+    }
+
+    #[test]
+    fn test_error_offset_after_unicode() {
+        let err = Regex::new("\u{e9}\u{e9}x{2").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::UnclosedRepetition { offset: 3 }));
+    }
+
+    #[test]
+    fn test_compiled_regex_is_reusable() {
+        let regex = Regex::new("^(\\w+) \\d+$").unwrap();
+        assert!(regex.is_match("apples 12"));
+        assert!(!regex.is_match("apples"));
+        assert!(regex.is_match("pears 3"));
+    }
+}
+
+#[cfg(test)]
+mod tests_regex_match {
+    use super::*;
+
+    #[test]
+    fn test_top_level_alternation() {
+        let regex = Regex::new("cat|dog").unwrap();
+        assert!(regex.is_match("hotdog"));
+        assert!(!regex.is_match("bird"));
+    }
+
+    #[test]
+    fn test_escaped_metacharacters() {
+        let regex = Regex::new("a\\.b\\(c\\)").unwrap();
+        assert!(regex.is_match("a.b(c)"));
+        assert!(!regex.is_match("axb(c)"));
+    }
+
+    #[test]
+    fn test_escaped_paren_inside_group() {
+        assert!(Regex::new("(\\)|x)+").unwrap().is_match(")x"));
+    }
+
+    #[test]
+    fn test_char_class_range() {
+        let regex = Regex::new("^[a-c0-9]+$").unwrap();
+        assert!(regex.is_match("abc123"));
+        assert!(!regex.is_match("abd"));
+    }
+
+    #[test]
+    fn test_quantifier_after_unicode_literal() {
+        assert!(Regex::new("^caf\u{e9}+$").unwrap().is_match("caf\u{e9}\u{e9}"));
+    }
+
+    #[test]
+    fn test_unset_group_backreference_fails() {
+        assert!(!Regex::new("(a)?b\\1").unwrap().is_match("b"));
+    }
+
+    #[test]
+    fn test_nested_star_terminates() {
+        assert!(Regex::new("(a*)*b").unwrap().is_match("aaab"));
+        assert!(!Regex::new("^(a*)*$").unwrap().is_match("aaab"));
+    }
+}
+
+#[cfg(test)]
+mod tests_regex_spans {
+    use super::*;
+
+    // ============================================================================
+    // find / captures Tests
+    // ============================================================================
+
+    #[test]
+    fn test_find_returns_byte_offsets() {
+        let regex = Regex::new("\\d+").unwrap();
+        let m = regex.find("caf\u{e9} 1234 x").unwrap();
+        assert_eq!((m.start(), m.end()), (6, 10));
+        assert_eq!(m.as_str(), "1234");
+    }
+
+    #[test]
+    fn test_find_no_match() {
+        assert!(Regex::new("\\d").unwrap().find("no digits").is_none());
+    }
+
+    #[test]
+    fn test_find_leftmost_greedy() {
+        let m = Regex::new("a+").unwrap().find("baaab aa").unwrap();
+        assert_eq!(m.range(), 1..4);
+    }
+
+    #[test]
+    fn test_find_empty_match() {
+        let m = Regex::new("x*").unwrap().find("abc").unwrap();
+        assert!(m.is_empty());
+        assert_eq!(m.start(), 0);
+    }
+
+    #[test]
+    fn test_captures_numbered_groups() {
+        let regex = Regex::new("(\\w+)=(\\d+)").unwrap();
+        let caps = regex.captures("set port=8080 now").unwrap();
+        assert_eq!(caps.len(), 3);
+        assert_eq!(&caps[0], "port=8080");
+        assert_eq!(&caps[1], "port");
+        assert_eq!(caps.get(2).unwrap().range(), 9..13);
+    }
+
+    #[test]
+    fn test_captures_non_participating_group() {
+        let caps = Regex::new("(a)|(b)").unwrap().captures("b").unwrap();
+        assert!(caps.get(1).is_none());
+        assert_eq!(caps.get(2).unwrap().as_str(), "b");
+    }
+
+    #[test]
+    fn test_captures_repeated_group_keeps_last_iteration() {
+        let caps = Regex::new("(cat|dog)+").unwrap().captures("catdog").unwrap();
+        assert_eq!(&caps[1], "dog");
+    }
+
+    #[test]
+    fn test_captures_backtracked_group() {
+        let caps = Regex::new("^(\\w+) and \\1$").unwrap().captures("dog and dog").unwrap();
+        assert_eq!(caps.get(1).unwrap().range(), 0..3);
+    }
+}
+
+#[cfg(test)]
+mod tests_regex_iter {
+    use super::*;
+
+    // ============================================================================
+    // find_iter / captures_iter Tests
+    // ============================================================================
+
+    fn all_matches<'h>(pattern: &str, haystack: &'h str) -> Vec<&'h str> {
+        Regex::new(pattern).unwrap().find_iter(haystack).map(|m| m.as_str()).collect()
+    }
+
+    #[test]
+    fn test_find_iter_digits() {
+        assert_eq!(all_matches("\\d+", "12 apples and 34 pears"), vec!["12", "34"]);
+    }
+
+    #[test]
+    fn test_find_iter_no_match() {
+        assert!(all_matches("\\d+", "apples").is_empty());
+    }
+
+    #[test]
+    fn test_find_iter_non_overlapping() {
+        assert_eq!(all_matches("aba", "ababababa"), vec!["aba", "aba"]);
+    }
+
+    #[test]
+    fn test_find_iter_empty_matches() {
+        let spans: Vec<_> = Regex::new("a*").unwrap().find_iter("baa").map(|m| m.range()).collect();
+        assert_eq!(spans, vec![0..0, 1..3]);
+    }
+
+    #[test]
+    fn test_find_iter_empty_pattern_on_unicode() {
+        let spans: Vec<_> = Regex::new("").unwrap().find_iter("\u{e9}a").map(|m| m.start()).collect();
+        assert_eq!(spans, vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn test_find_iter_start_anchor_only_once() {
+        assert_eq!(all_matches("^a", "aaa"), vec!["a"]);
+    }
+
+    #[test]
+    fn test_captures_iter_groups() {
+        let regex = Regex::new("(\\w+)=(\\d+)").unwrap();
+        let pairs: Vec<(String, String)> = regex
+            .captures_iter("a=1, b=22, c=x")
+            .map(|caps| (caps[1].to_string(), caps[2].to_string()))
+            .collect();
+        assert_eq!(pairs, vec![("a".to_string(), "1".to_string()), ("b".to_string(), "22".to_string())]);
+    }
+}
+
+#[cfg(test)]
+mod tests_regex_many {
+    use super::*;
+
+    #[test]
+    fn test_new_many_matches_any_pattern() {
+        let regex = Regex::new_many(["^cat", "dog$"]).unwrap();
+        assert!(regex.is_match("catalog"));
+        assert!(regex.is_match("hotdog"));
+        assert!(!regex.is_match("a cat and a dog!"));
+    }
+
+    #[test]
+    fn test_new_many_keeps_back_references_local() {
+        let regex = Regex::new_many(["(a)\\1", "(b)\\1"]).unwrap();
+        assert_eq!(regex.group_count(), 2);
+        assert!(regex.is_match("xbb"));
+        assert!(!regex.is_match("ab"));
+        assert_eq!(regex.captures("bb").unwrap().get(2).unwrap().as_str(), "b");
+    }
+
+    #[test]
+    fn test_new_many_without_patterns_never_matches() {
+        assert!(!Regex::new_many(Vec::<String>::new()).unwrap().is_match(""));
+    }
+
+    #[test]
+    fn test_new_many_error_offset_spans_patterns() {
+        let err = Regex::new_many(["ab", "c)"]).unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::UnmatchedParenthesis { offset: 4 }));
+    }
+}
+
+#[cfg(test)]
+mod tests_case_insensitive {
+    use super::*;
+    use codecrafters_grep::RegexBuilder;
+
+    fn ci(pattern: &str) -> Regex {
+        RegexBuilder::new().case_insensitive(true).build(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_builder_literal() {
+        assert!(ci("hello").is_match("Say HeLLo"));
+        assert!(!Regex::new("hello").unwrap().is_match("Say HeLLo"));
+    }
+
+    #[test]
+    fn test_builder_char_class_and_range() {
+        assert!(ci("^[a-c]+$").is_match("AbC"));
+        assert!(ci("^[A-C]+$").is_match("abc"));
+        assert!(ci("[^a]").is_match("b"));
+        assert!(!ci("^[^a]$").is_match("A"));
+    }
+
+    #[test]
+    fn test_builder_backreference() {
+        assert!(ci("(\\w+) and \\1").is_match("Cat and cAT"));
+        assert!(!Regex::new("(\\w+) and \\1").unwrap().is_match("Cat and cAT"));
+    }
+
+    #[test]
+    fn test_unicode_simple_folding() {
+        assert!(ci("stra\u{df}e").is_match("STRA\u{df}E"));
+        assert!(ci("\u{3c3}").is_match("\u{3a3}"));
+        assert!(ci("\u{3c3}").is_match("\u{3c2}"));
+        // KELVIN SIGN folds to plain k
+        assert_eq!(ci("k+").find("\u{212a}k").unwrap().range(), 0..4);
+    }
+
+    #[test]
+    fn test_inline_flag_applies_to_rest_of_pattern() {
+        let regex = Regex::new("ab(?i)cd").unwrap();
+        assert!(regex.is_match("abCD"));
+        assert!(!regex.is_match("ABcd"));
+    }
+
+    #[test]
+    fn test_inline_flag_is_scoped_to_group() {
+        let regex = Regex::new("((?i)a)b").unwrap();
+        assert!(regex.is_match("Ab"));
+        assert!(!regex.is_match("AB"));
+    }
+
+    #[test]
+    fn test_inline_flag_can_be_turned_off() {
+        let regex = ci("a(?-i)b");
+        assert!(regex.is_match("Ab"));
+        assert!(!regex.is_match("AB"));
+    }
+
+    #[test]
+    fn test_scoped_flag_group_is_not_capturing() {
+        let regex = Regex::new("(?i:ab)(c)").unwrap();
+        assert_eq!(regex.group_count(), 1);
+        assert_eq!(&regex.captures("xABc").unwrap()[1], "c");
+    }
+
+    #[test]
+    fn test_unknown_flag() {
+        assert_eq!(
+            Regex::new("a(?x)").unwrap_err(),
+            Error::Parse(ParseError::UnknownFlag { offset: 3, flag: 'x' })
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_bytes {
+    use super::*;
+    use codecrafters_grep::bytes;
+
+    // ============================================================================
+    // bytes::Regex Tests
+    // ============================================================================
+
+    fn raw(pattern: &str) -> bytes::Regex {
+        bytes::RegexBuilder::new().utf8(false).build(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_utf8_mode_decodes_codepoints() {
+        let regex = bytes::Regex::new("^caf.$").unwrap();
+        assert!(regex.is_match("café".as_bytes()));
+        assert!(regex.is_utf8());
+    }
+
+    #[test]
+    fn test_utf8_mode_skips_invalid_bytes() {
+        let regex = bytes::Regex::new(r"\w+").unwrap();
+        let haystack = b"\xffcaf\xe9 ok";
+        let found: Vec<_> = regex.find_iter(haystack).map(|m| m.as_bytes()).collect();
+        assert_eq!(found, vec![&b"caf"[..], &b"ok"[..]]);
+    }
+
+    #[test]
+    fn test_utf8_mode_dot_does_not_match_invalid_byte() {
+        let regex = bytes::Regex::new("^caf.$").unwrap();
+        assert!(!regex.is_match(b"caf\xe9"));
+    }
+
+    #[test]
+    fn test_raw_mode_dot_matches_any_byte() {
+        let regex = raw("^caf.$");
+        assert!(regex.is_match(b"caf\xe9"));
+        assert!(!regex.is_match("café".as_bytes()));
+        assert!(!raw("^.$").is_match(b"\n"));
+    }
+
+    #[test]
+    fn test_raw_mode_negated_class_matches_high_bytes() {
+        let regex = raw("[^a-z]+");
+        assert_eq!(regex.find(b"ab\xe9\xe8cd").unwrap().range(), 2..4);
+        assert!(!bytes::Regex::new("[^a-z]").unwrap().is_match(b"ab\xe9"));
+    }
+
+    #[test]
+    fn test_literal_matches_in_invalid_text() {
+        let regex = bytes::Regex::new("error").unwrap();
+        let found = regex.find(b"\xfe\xff error: \xc3").unwrap();
+        assert_eq!(found.range(), 3..8);
+    }
+
+    #[test]
+    fn test_captures_and_backreference() {
+        let regex = bytes::Regex::new(r"(\w+)=\1").unwrap();
+        let captures = regex.captures(b"\xff x=x").unwrap();
+        assert_eq!(&captures[0], b"x=x");
+        assert_eq!(&captures[1], b"x");
+    }
+
+    #[test]
+    fn test_raw_mode_case_insensitive_folds_ascii() {
+        let regex = bytes::RegexBuilder::new().utf8(false).case_insensitive(true).build("caf").unwrap();
+        assert!(regex.is_match(b"\xe9CAF"));
+    }
+
+    #[test]
+    fn test_find_iter_empty_matches_step_over_invalid_bytes() {
+        let regex = bytes::Regex::new("x*").unwrap();
+        let spans: Vec<_> = regex.find_iter(b"\xffx").map(|m| m.range()).collect();
+        assert_eq!(spans, vec![0..0, 1..2]);
+    }
+
+    #[test]
+    fn test_parse_errors_are_shared() {
+        let err = bytes::Regex::new("a(b").unwrap_err();
+        assert!(matches!(err, Error::Parse(ParseError::UnclosedParenthesis { .. })));
+    }
+
+    fn multi_line(pattern: &str) -> bytes::Regex {
+        bytes::RegexBuilder::new().multi_line(true).build(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_multi_line_anchors_match_at_every_line() {
+        let regex = multi_line(r"^b\w*$");
+        let found: Vec<_> = regex.find_iter(b"abc\nbcd\r\nbe\n").map(|m| m.as_bytes()).collect();
+        assert_eq!(found, vec![&b"bcd"[..], &b"be"[..]]);
+        assert!(!bytes::Regex::new("^b").unwrap().is_match(b"a\nb"));
+    }
+
+    #[test]
+    fn test_multi_line_matches_stay_within_a_line() {
+        assert!(!multi_line(r"a[^x]b").is_match(b"a\nb"));
+        assert!(!multi_line(r"a\sb").is_match(b"a\nb"));
+        assert!(!multi_line("a.b").is_match(b"a\nb"));
+        assert_eq!(multi_line(r"\w+$").find(b"one\ntwo").unwrap().as_bytes(), b"one");
+    }
+}
+
+#[cfg(test)]
+mod tests_literals {
+    use super::*;
+    use codecrafters_grep::bytes;
+    use codecrafters_grep::literal::required_literals;
+
+    // ============================================================================
+    // Required Literal Tests
+    // ============================================================================
+
+    fn literals(pattern: &str) -> Option<Vec<String>> {
+        required_literals(Regex::new(pattern).unwrap().ast())
+    }
+
+    fn strings(literals: &[&str]) -> Option<Vec<String>> {
+        Some(literals.iter().map(|literal| literal.to_string()).collect())
+    }
+
+    #[test]
+    fn test_prefix_literal_is_required() {
+        assert_eq!(literals(r"ERROR \d+"), strings(&["ERROR "]));
+        assert_eq!(literals("^abc$"), strings(&["abc"]));
+    }
+
+    #[test]
+    fn test_longest_inner_literal_is_chosen() {
+        assert_eq!(literals(r"\d+ms timeout \w+"), strings(&["ms timeout "]));
+        assert_eq!(literals("a+bcd"), strings(&["bcd"]));
+    }
+
+    #[test]
+    fn test_alternation_gives_a_set() {
+        assert_eq!(literals("(warn|error): "), strings(&["error: ", "warn: "]));
+        assert_eq!(literals(r"cat\d|dog\d"), strings(&["cat", "dog"]));
+        assert_eq!(literals("x{3}"), strings(&["xxx"]));
+    }
+
+    #[test]
+    fn test_long_repetition_gives_a_prefix() {
+        assert_eq!(literals("a{32767}"), strings(&[&"a".repeat(64)]));
+        assert_eq!(literals("x(ab){40}"), strings(&[&"ab".repeat(32)]));
+        let regex = Regex::new("b{1000}").unwrap();
+        assert!(regex.is_match(&"b".repeat(1000)));
+        assert!(!regex.is_match(&"b".repeat(999)));
+    }
+
+    #[test]
+    fn test_nothing_required() {
+        assert_eq!(literals(r"\d+"), None);
+        assert_eq!(literals("a*"), None);
+        assert_eq!(literals("foo|.+"), None);
+        assert_eq!(literals("(?i)error"), None);
+    }
+
+    #[test]
+    fn test_prefiltered_search_finds_same_matches() {
+        let regex = Regex::new(r"(\w+)@(example|test)\.com").unwrap();
+        let haystack = "mail bob@test.com or ann@example.com, not x@test.org";
+        let found: Vec<_> = regex.find_iter(haystack).map(|m| m.as_str()).collect();
+        assert_eq!(found, vec!["bob@test.com", "ann@example.com"]);
+        assert!(!regex.is_match("nothing here"));
+    }
+
+    #[test]
+    fn test_multi_line_search_skips_lines_without_literal() {
+        let regex = bytes::RegexBuilder::new().multi_line(true).build(r"^\w+ ERROR$").unwrap();
+        let haystack = b"a ERROR x\nb INFO\nc ERROR\nERROR\n";
+        let found: Vec<_> = regex.find_iter(haystack).map(|m| m.range()).collect();
+        assert_eq!(found, vec![17..24]);
+    }
+}
+
+#[cfg(test)]
+mod tests_linear_time {
+    use super::*;
+    use codecrafters_grep::RegexBuilder;
+
+    // ============================================================================
+    // Pathological Pattern Tests
+    // ============================================================================
+
+    #[test]
+    fn test_nested_repetition_fails_fast() {
+        let haystack = "a".repeat(64);
+        assert!(!Regex::new("(a+)+b").unwrap().is_match(&haystack));
+        assert!(!Regex::new("(a|aa)*c").unwrap().is_match(&haystack));
+        assert!(!Regex::new("(a*)*(b|c)").unwrap().is_match(&haystack));
+    }
+
+    #[test]
+    fn test_nested_repetition_still_captures() {
+        let captures = Regex::new("(a+)+b").unwrap().captures("xaaab").unwrap();
+        assert_eq!(&captures[0], "aaab");
+        assert_eq!(&captures[1], "aaa");
+    }
+
+    #[test]
+    fn test_program_size_is_limited() {
+        let err = Regex::new("(a{1000}){1000}").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::TooLarge { offset: 0, limit: 250_000 }));
+        assert!(Regex::new(r"(\w{1000}){1000}\1").is_err());
+        let regex = RegexBuilder::new().size_limit(2_000_000).build("(a{1000}){1000}").unwrap();
+        assert!(!regex.is_match("aaa"));
+        assert!(RegexBuilder::new().size_limit(10).build("a{8}").is_err());
+        assert!(Regex::new(".{1,10000}x").unwrap().is_match("abcx"));
+    }
+
+    #[test]
+    fn test_backreferences_still_match() {
+        let regex = Regex::new(r"(a+)+\1b").unwrap();
+        assert_eq!(regex.find("aaab").unwrap().as_str(), "aaab");
+    }
+}
+
+#[cfg(test)]
+mod tests_lazy_dfa {
+    use super::*;
+    use codecrafters_grep::bytes;
+
+    // ============================================================================
+    // is_match / shortest_match_at Tests
+    // ============================================================================
+
+    #[test]
+    fn test_is_match_agrees_with_find() {
+        let haystacks = ["", "abc", "xyz\n", "a1b2", "ünïcödé", "ab\r"];
+        for pattern in ["^a", "c$", r"\d", "(b|y)+", "^$", "é$", "(?i)Ä|b"] {
+            let regex = Regex::new(pattern).unwrap();
+            for haystack in haystacks {
+                assert_eq!(regex.is_match(haystack), regex.find(haystack).is_some(), "{} on {:?}", pattern, haystack);
+            }
+        }
+    }
+
+    #[test]
+    fn test_state_explosion_clears_the_cache() {
+        // needs a state for every combination of the last 13 chars
+        let regex = Regex::new("(a|b)*a(a|b){12}").unwrap();
+        let mut haystack: String = (0..5000).map(|index| if index * 7 % 11 < 5 { 'a' } else { 'b' }).collect();
+        assert!(regex.is_match(&haystack));
+        haystack = haystack.replace('a', "b");
+        assert!(!regex.is_match(&haystack));
+
+        // a match only ends past the last of many different windows
+        let regex = bytes::Regex::new("a(a|b){12}c").unwrap();
+        let mut seed = 1u32;
+        let mut haystack: Vec<u8> = (0..20_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                if seed >> 16 & 1 == 1 { b'a' } else { b'b' }
+            })
+            .collect();
+        haystack[20_000 - 13] = b'a';
+        haystack.push(b'c');
+        for _ in 0..2 {
+            assert_eq!(regex.shortest_match_at(&haystack, 0), Some(haystack.len()));
+        }
+    }
+
+    #[test]
+    fn test_shortest_match_at() {
+        let regex = bytes::Regex::new("a+").unwrap();
+        assert_eq!(regex.shortest_match_at(b"xxaaa", 0), Some(3));
+        assert_eq!(regex.find_at(b"xxaaa", 0).unwrap().end(), 5);
+        assert_eq!(regex.shortest_match_at(b"xxaaa", 6), None);
+        let regex = bytes::RegexBuilder::new().multi_line(true).build("^b$").unwrap();
+        assert_eq!(regex.shortest_match_at(b"ab\nb\r\nb", 0), Some(4));
+    }
+
+    #[test]
+    fn test_regex_is_shared_between_threads() {
+        let regex = Regex::new(r"\d{3}-\d{4}").unwrap();
+        std::thread::scope(|scope| {
+            for index in 0..4 {
+                let regex = &regex;
+                scope.spawn(move || {
+                    for line in 0..200 {
+                        let haystack = format!("call {}{}-{:04} now", index, line, line);
+                        assert_eq!(regex.is_match(&haystack), line >= 10);
+                        let end = regex.find(&haystack).map(|found| found.end());
+                        assert_eq!(end, (line >= 10).then_some(haystack.len() - 4));
+                    }
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests_backtracking_limits {
+    use super::*;
+    use codecrafters_grep::{bytes, MatchError, RegexBuilder};
+
+    // ============================================================================
+    // Step Budget / Recursion Limit Tests
+    // ============================================================================
+
+    #[test]
+    fn test_step_budget_exceeded() {
+        let regex = RegexBuilder::new().step_budget(10_000).build(r"((x|xx)*)*\1[yz]").unwrap();
+        let haystack = format!("--{}", "x".repeat(30));
+        assert_eq!(regex.try_is_match(&haystack), Err(MatchError::BudgetExceeded { offset: 2 }));
+        assert!(!regex.is_match(&haystack));
+        assert!(regex.find(&haystack).is_none());
+    }
+
+    #[test]
+    fn test_recursion_limit_exceeded() {
+        let regex = RegexBuilder::new().recursion_limit(100).build(r"(a)(a|b)*\1d").unwrap();
+        let haystack = format!("{}d", "a".repeat(500));
+        let err = regex.try_find_at(&haystack, 0).unwrap_err();
+        assert_eq!(err, MatchError::RecursionLimitExceeded { offset: 0 });
+        assert_eq!(err.offset(), 0);
+        assert_eq!(err.to_string(), "backtracking recursion limit exceeded");
+    }
+
+    #[test]
+    fn test_default_limits_survive_a_spawned_thread() {
+        let regex = Regex::new(r"(a)(a|b)*\1d").unwrap();
+        let haystack = format!("{}d", "a".repeat(100_000));
+        let result = std::thread::spawn(move || regex.try_is_match(&haystack)).join().unwrap();
+        assert!(matches!(result, Err(MatchError::RecursionLimitExceeded { .. })));
+    }
+
+    #[test]
+    fn test_limits_do_not_get_in_the_way() {
+        let regex = Regex::new(r"(\w+) \1").unwrap();
+        assert_eq!(regex.try_find_at("say hello hello", 0).unwrap().unwrap().as_str(), "hello hello");
+        // patterns without back references never backtrack
+        let regex = RegexBuilder::new().step_budget(1).recursion_limit(1).build("(a|aa)*b").unwrap();
+        assert_eq!(regex.try_is_match(&format!("{}b", "a".repeat(1000))), Ok(true));
+    }
+
+    #[test]
+    fn test_long_lines_still_match() {
+        let regex = Regex::new(r"(\w+) \1").unwrap();
+        let word_first = format!("{} foo foo", "w".repeat(1000));
+        let many_words = (0..2000).map(|index| format!("word{} ", index)).collect::<String>() + "foo foo";
+        assert!(many_words.len() > 10_000);
+        let trailing = format!("foo foo {}", "y".repeat(10_000));
+        // a spawned thread has the smallest stack the defaults have to fit
+        std::thread::spawn(move || {
+            assert_eq!(regex.try_find_at(&word_first, 0).unwrap().unwrap().as_str(), "foo foo");
+            assert_eq!(regex.try_find_at(&many_words, 0).unwrap().unwrap().as_str(), "foo foo");
+            let regex = Regex::new(r"(\w+) \1.*").unwrap();
+            assert_eq!(regex.try_find_at(&trailing, 0).unwrap().unwrap().end(), trailing.len());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_step_budget_covers_the_whole_search() {
+        // each attempt is well within the budget, all of them together are not
+        let regex = RegexBuilder::new().step_budget(100_000).build(r"(\w+) \1").unwrap();
+        let word = format!("{} x", "w".repeat(1000));
+        assert!(matches!(regex.try_is_match(&word), Err(MatchError::BudgetExceeded { .. })));
+        // but in multi-line mode each line has a budget of its own
+        let regex = bytes::RegexBuilder::new().multi_line(true).step_budget(100_000).build(r"(\w+) \1").unwrap();
+        let lines = format!("{} x\n", "w".repeat(100)).repeat(50) + "foo foo\n";
+        assert_eq!(regex.try_find_at(lines.as_bytes(), 0).unwrap().unwrap().as_bytes(), b"foo foo");
+    }
+
+    #[test]
+    fn test_bytes_limits_report_the_line() {
+        let regex = bytes::RegexBuilder::new().multi_line(true).recursion_limit(100).build(r"(a)(a|b)*\1d").unwrap();
+        let haystack = format!("ab d\n{}d\naad\n", "a".repeat(500));
+        let err = regex.try_shortest_match_at(haystack.as_bytes(), 0).unwrap_err();
+        assert_eq!(err, MatchError::RecursionLimitExceeded { offset: 5 });
+        assert_eq!(regex.try_find_at(haystack.as_bytes(), 506).unwrap().unwrap().range(), 507..510);
+    }
+}